@@ -0,0 +1,128 @@
+const VERSION: u8 = 0x1c;
+
+const HASH_LENGTH: usize = 32;
+const CHECKSUM_LENGTH: usize = 4;
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::keys::PublicKey;
+
+use crate::utils::sha256;
+
+//base58check encoding of version byte + public key hash + checksum
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Address(Vec<u8>);
+
+#[derive(Debug, PartialEq)]
+pub enum AddressError{
+    InvalidEncoding(String),
+    InvalidLength(usize),
+    InvalidChecksum,
+    InvalidVersion(u8),
+}
+
+impl fmt::Display for AddressError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            AddressError::InvalidEncoding(e) => write!(f, "Address is not valid base58: {}", e),
+            AddressError::InvalidLength(length) => write!(f, "Address has invalid length: {} bytes", length),
+            AddressError::InvalidChecksum => write!(f, "Address checksum does not match, check for typos"),
+            AddressError::InvalidVersion(version) => write!(f, "Address has unknown version: {}", version),
+        }
+    }
+}
+
+impl std::error::Error for AddressError{}
+
+fn checksum(payload: &[u8]) -> Vec<u8>{
+    sha256(sha256(payload.to_vec()))[..CHECKSUM_LENGTH].to_vec()
+}
+
+impl Address{
+    pub fn from_public_key_hash(public_key_hash: Vec<u8>) -> Result<Self, AddressError>{
+        if public_key_hash.len() != HASH_LENGTH{
+            return Err(AddressError::InvalidLength(public_key_hash.len()))
+        }
+        Ok(Self(public_key_hash))
+    }
+
+    pub fn get_public_key_hash(&self) -> Vec<u8>{
+        self.0.clone()
+    }
+}
+
+impl From<&PublicKey> for Address{
+    fn from(public_key: &PublicKey) -> Self{
+        Self(public_key.to_hash())
+    }
+}
+
+impl fmt::Display for Address{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut payload = vec![VERSION];
+        payload.extend(&self.0);
+        payload.extend(checksum(&payload));
+        write!(f, "{}", bs58::encode(payload).into_string())
+    }
+}
+
+impl FromStr for Address{
+    type Err = AddressError;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        let bytes = bs58::decode(data.trim())
+            .into_vec()
+            .map_err(|e| AddressError::InvalidEncoding(e.to_string()))?;
+
+        if bytes.len() != 1 + HASH_LENGTH + CHECKSUM_LENGTH{
+            return Err(AddressError::InvalidLength(bytes.len()))
+        }
+        let (payload, check) = bytes.split_at(bytes.len() - CHECKSUM_LENGTH);
+        if checksum(payload) != check{
+            return Err(AddressError::InvalidChecksum)
+        }
+        if payload[0] != VERSION{
+            return Err(AddressError::InvalidVersion(payload[0]))
+        }
+        Ok(Self(payload[1..].to_vec()))
+    }
+}
+
+impl Serialize for Address{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl <'de>Deserialize<'de> for Address{
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de> {
+        let data = String::deserialize(deserializer)?;
+        use serde::de::Error;
+        data.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn round_trip_and_typo(){
+        let address = Address::from_public_key_hash(sha256(b"comp-coin".to_vec())).unwrap();
+        let encoded = address.to_string();
+        assert_eq!(encoded.parse::<Address>().unwrap(), address);
+
+        let mut typo: Vec<char> = encoded.chars().collect();
+        typo[10] = if typo[10] == 'a' {'b'} else {'a'};
+        let typo: String = typo.into_iter().collect();
+        assert_eq!(typo.parse::<Address>(), Err(AddressError::InvalidChecksum));
+
+        assert!(matches!("0OIl".parse::<Address>(), Err(AddressError::InvalidEncoding(_))));
+    }
+}
@@ -1,16 +1,47 @@
-use std::sync::{Arc, atomic::{self, AtomicBool}};
+use std::fmt;
 
-use log::{info, warn};
 use serde::{Serialize, Deserialize};
-use tokio::sync::mpsc; 
 
 use super::transaction::Transaction;
 
 //delete private key import later
 use crate::{
-    block::keys::{PrivateKey}, node::NetworkCommand, utils::{format_number, generate_nonce, get_timestamp, sha256}
+    block::keys::{PrivateKey}, utils::{generate_nonce, get_timestamp, sha256}
 };
 
+#[derive(Debug, PartialEq)]
+pub enum BlockError{
+    InvalidEncoding(String),
+    WrongHeight{
+        expected: usize,
+        found: usize,
+    },
+    WrongPrevHash,
+    WrongDifficulty{
+        expected: usize,
+        found: usize,
+    },
+    InsufficientWork,
+    WrongMerkleRoot,
+    InvalidTransactions,
+}
+
+impl fmt::Display for BlockError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            BlockError::InvalidEncoding(e) => write!(f, "Block is not encoded correctly: {}", e),
+            BlockError::WrongHeight { expected, found } => write!(f, "Block height is {}, expected {}", found, expected),
+            BlockError::WrongPrevHash => write!(f, "Block does not build on the current tip"),
+            BlockError::WrongDifficulty { expected, found } => write!(f, "Block difficulty is {}, expected {}", found, expected),
+            BlockError::InsufficientWork => write!(f, "Block hash does not meet its difficulty"),
+            BlockError::WrongMerkleRoot => write!(f, "Block merkle root does not match its transactions"),
+            BlockError::InvalidTransactions => write!(f, "Block contains invalid transactions or coinbase"),
+        }
+    }
+}
+
+impl std::error::Error for BlockError{}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Block{
     header: BlockHeader,
@@ -55,8 +86,8 @@ impl BlockHeader{
         self.difficulty
     }
 
-    fn update_nonce(&mut self){
-        self.nonce = generate_nonce();
+    fn get_timestamp(&self) -> usize{
+        self.timestamp
     }
 }
 
@@ -72,7 +103,7 @@ impl Block{
         Self { 
             header: BlockHeader::new(
                 prev_hash, 
-                Self::get_merkle_root(&transactions), 
+                Self::merkle_root(&transactions), 
                 difficulty, 
                 version, 
                 height), 
@@ -84,14 +115,55 @@ impl Block{
         return self.header.get_height()
     }
 
-    fn get_difficulty(&self) -> usize{
+    pub fn get_difficulty(&self) -> usize{
         self.header.get_difficulty()
     }
 
+    pub fn get_prev_hash(&self) -> Vec<u8>{
+        self.header.prev_hash.clone()
+    }
+
+    pub fn get_merkle_root(&self) -> Vec<u8>{
+        self.header.merkle_root.clone()
+    }
+
+    pub fn get_version(&self) -> usize{
+        self.header.version
+    }
+
+    //checks that need nothing but the block itself
+    pub fn check_header(&self) -> Result<(), BlockError>{
+        if Self::merkle_root(&self.transactions) != self.header.merkle_root{
+            return Err(BlockError::WrongMerkleRoot)
+        }
+        if !Self::meets_diffculty(self.calculate_hash(), self.get_difficulty()){
+            return Err(BlockError::InsufficientWork)
+        }
+        Ok(())
+    }
+
+    pub fn to_hex(&self) -> String{
+        hex::encode(self.to_bytes())
+    }
+
+    pub fn from_hex(data: &str) -> Result<Self, BlockError>{
+        let bytes = hex::decode(data.trim()).map_err(|e| BlockError::InvalidEncoding(e.to_string()))?;
+        postcard::from_bytes(&bytes).map_err(|e| BlockError::InvalidEncoding(e.to_string()))
+    }
+
+    pub fn get_timestamp(&self) -> usize{
+        self.header.get_timestamp()
+    }
+
     pub fn get_transactions(&self) -> Vec<Transaction>{
         self.transactions.clone()
     }
 
+    //everything but the coinbase, as the transactions were relayed before the miner added its fee output
+    pub fn get_relayed_transactions(&self) -> Vec<Transaction>{
+        self.transactions.iter().skip(1).map(Transaction::without_fee).collect()
+    }
+
     fn meets_diffculty(hash: Vec<u8>, difficulty: usize) -> bool{
         hash.iter().take(difficulty).all(|&x| x == 0 )
     }
@@ -104,42 +176,33 @@ impl Block{
         sha256(self.to_bytes())
     }
 
-    fn update_nonce(&mut self){
-        self.header.update_nonce();
+    pub fn set_nonce(&mut self, nonce: Vec<u8>){
+        self.header.nonce = nonce;
     }
 
-    pub fn mine(
-        &mut self, 
-        stop: Arc<AtomicBool>, 
-        id: usize,
-        network_tx: mpsc::Sender<NetworkCommand>
-    ){
-        info!("Thread: {} started mining", &id);
-
-        let mut count: usize = 1;
-
-        while !stop.load(atomic::Ordering::Relaxed){
-
-            self.update_nonce();
-            if Block::meets_diffculty(self.calculate_hash(), self.get_difficulty()){
-                info!("Mined block: {}", self.get_height());
-                if let Err(e) = network_tx.try_send(NetworkCommand::Block(self.clone())){
-                    warn!("Unable to communicate on network channel: {}", e);
-                }
-            }
-            
-            if count%250_000 == 0 && id == 0{
-                info!("Each thread tried: {} blocks", format_number(count));
-            }
-            count += 1;
-        }
-
+    pub fn meets_difficulty(&self, difficulty: usize) -> bool{
+        Self::meets_diffculty(self.calculate_hash(), difficulty)
     }
 
-    fn get_merkle_root(transactions: &Vec<Transaction>) -> Vec<u8>{
+    fn merkle_root(transactions: &[Transaction]) -> Vec<u8>{
         Self::rec_merkle_root(transactions.iter().map(|tx| tx.to_bytes()).collect())
     }
 
+    //siblings of the coinbase on the way up, the root is each one hashed onto the coinbase bytes in turn,
+    //so a miner can swap in its own coinbase without the other transactions
+    //a block with only the coinbase has an empty branch and its root is sha256(coinbase || coinbase)
+    pub fn get_merkle_branch(&self) -> Vec<Vec<u8>>{
+        let mut level: Vec<Vec<u8>> = self.transactions.iter().map(|tx| tx.to_bytes()).collect();
+        let mut branch = Vec::new();
+        while level.len() > 1{
+            branch.push(level[1].clone());
+            level = level.chunks(2)
+                .map(|pair| sha256(if pair.len() == 1 {pair[0].repeat(2)} else {pair.concat()}))
+                .collect();
+        }
+        branch
+    }
+
     fn rec_merkle_root(transactions: Vec<Vec<u8>>) -> Vec<u8>{
         match transactions.len(){
             0 => sha256(b"Hello World".to_vec()),
@@ -195,4 +258,42 @@ impl Block{
         transactions: vec![Transaction::reward(100, PrivateKey::new().get_public_key(), 0)]
     }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn merkle_branch_rebuilds_root(){
+        for count in 1..8{
+            let transactions: Vec<Transaction> = (0..count)
+                .map(|value| Transaction::reward(value, PrivateKey::new().get_public_key(), 0))
+                .collect();
+            let block = Block::new(0, 0, 0, transactions.clone(), vec![0; 32]);
+
+            let branch = block.get_merkle_branch();
+            let coinbase = transactions[0].to_bytes();
+            let root = if branch.is_empty(){
+                sha256(coinbase.repeat(2))
+            }else{
+                branch.into_iter().fold(coinbase, |node, sibling| sha256([node, sibling].concat()))
+            };
+            assert_eq!(root, block.get_merkle_root());
+        }
+    }
+
+    #[test]
+    fn header_checks_work_and_merkle_root(){
+        let coinbase = Transaction::reward(10, PrivateKey::new().get_public_key(), 0);
+        let block = Block::new(0, 0, 0, vec![coinbase], vec![0; 32]);
+        assert_eq!(Block::from_hex(&block.to_hex()).unwrap().check_header(), Ok(()));
+
+        let mut tampered = block.clone();
+        tampered.transactions.push(Transaction::reward(5, PrivateKey::new().get_public_key(), 0));
+        assert_eq!(tampered.check_header(), Err(BlockError::WrongMerkleRoot));
+
+        let mut hard = block;
+        hard.header.difficulty = 32;
+        assert_eq!(hard.check_header(), Err(BlockError::InsufficientWork));
+    }
+}
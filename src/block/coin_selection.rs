@@ -0,0 +1,181 @@
+pub const DUST_LIMIT: usize = 2;
+
+const MAX_TRIES: usize = 100_000;
+
+use std::cmp::Reverse;
+
+use rand::seq::SliceRandom;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoinSelection{
+    #[default]
+    LargestFirst,
+    SmallestFirst,
+    BranchAndBound,
+    Random,
+}
+
+#[derive(Clone, Debug)]
+pub struct Candidate{
+    pub hash: Vec<u8>,
+    pub index: usize,
+    pub value: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Selection{
+    pub inputs: Vec<Candidate>,
+    pub fee: usize,
+    pub change: usize,
+}
+
+impl CoinSelection{
+    //target includes the fee, returns None if the candidates cannot cover it
+    pub fn select(&self, mut candidates: Vec<Candidate>, target: usize, fee: usize) -> Option<Selection>{
+        match self{
+            CoinSelection::LargestFirst => {
+                candidates.sort_by_key(|candidate| Reverse(candidate.value));
+            }
+            CoinSelection::SmallestFirst => {
+                candidates.sort_by_key(|candidate| candidate.value);
+            }
+            CoinSelection::Random => {
+                candidates.shuffle(&mut rand::rng());
+            }
+            CoinSelection::BranchAndBound => {
+                candidates.sort_by_key(|candidate| Reverse(candidate.value));
+                if let Some(inputs) = branch_and_bound(&candidates, target){
+                    return Some(Self::finish(inputs, target, fee))
+                }
+            }
+        }
+
+        let mut inputs = Vec::new();
+        let mut total = 0;
+        for candidate in candidates{
+            if total >= target{
+                break
+            }
+            total += candidate.value;
+            inputs.push(candidate);
+        }
+
+        if total < target{
+            return None
+        }
+        Some(Self::finish(inputs, target, fee))
+    }
+
+    //forced inputs are always spent, the strategy only tops them up when they fall short
+    pub fn select_with_inputs(
+        &self, 
+        forced: Vec<Candidate>, 
+        candidates: Vec<Candidate>, 
+        target: usize, 
+        fee: usize
+    ) -> Option<Selection>{
+        let forced_value: usize = forced.iter().map(|input| input.value).sum();
+        if forced_value >= target{
+            return Some(Self::finish(forced, target, fee))
+        }
+
+        let candidates = candidates.into_iter()
+            .filter(|candidate| !forced.iter().any(
+                |input| input.hash == candidate.hash && input.index == candidate.index
+            ))
+            .collect();
+        let extra = self.select(candidates, target - forced_value, 0)?;
+
+        let mut inputs = forced;
+        inputs.extend(extra.inputs);
+        Some(Self::finish(inputs, target, fee))
+    }
+
+    //change below the dust limit is not worth an output, so it goes to the fee
+    fn finish(inputs: Vec<Candidate>, target: usize, fee: usize) -> Selection{
+        let total: usize = inputs.iter().map(|input| input.value).sum();
+        let change = total - target;
+
+        if change < DUST_LIMIT{
+            Selection { inputs, fee: fee + change, change: 0 }
+        }else{
+            Selection { inputs, fee, change }
+        }
+    }
+}
+
+//searches for inputs summing to the target without needing a change output
+fn branch_and_bound(candidates: &[Candidate], target: usize) -> Option<Vec<Candidate>>{
+    let values: Vec<usize> = candidates.iter().map(|candidate| candidate.value).collect();
+    let mut remaining = vec![0; values.len() + 1];
+    for index in (0..values.len()).rev(){
+        remaining[index] = remaining[index + 1] + values[index];
+    }
+
+    let mut selected = Vec::new();
+    let mut tries = MAX_TRIES;
+
+    if search(&values, &remaining, 0, 0, target, &mut selected, &mut tries){
+        Some(selected.iter().map(|index| candidates[*index].clone()).collect())
+    }else{
+        None
+    }
+}
+
+fn search(
+    values: &[usize],
+    remaining: &[usize],
+    index: usize,
+    current: usize,
+    target: usize,
+    selected: &mut Vec<usize>,
+    tries: &mut usize,
+) -> bool{
+    if current >= target{
+        return current < target + DUST_LIMIT
+    }
+    if index == values.len() || current + remaining[index] < target || *tries == 0{
+        return false
+    }
+    *tries -= 1;
+
+    selected.push(index);
+    if search(values, remaining, index + 1, current + values[index], target, selected, tries){
+        return true
+    }
+    selected.pop();
+
+    search(values, remaining, index + 1, current, target, selected, tries)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn candidates(values: &[usize]) -> Vec<Candidate>{
+        values.iter().enumerate().map(|(index, value)| Candidate {
+            hash: vec![index as u8],
+            index,
+            value: *value
+        }).collect()
+    }
+
+    #[test]
+    fn branch_and_bound_finds_exact_match(){
+        let selection = CoinSelection::BranchAndBound
+            .select(candidates(&[10, 7, 5, 3]), 8, 1)
+            .unwrap();
+
+        let total: usize = selection.inputs.iter().map(|input| input.value).sum();
+        assert_eq!(total, 8);
+        assert_eq!(selection.change, 0);
+    }
+
+    #[test]
+    fn insufficient_funds(){
+        assert!(CoinSelection::LargestFirst.select(candidates(&[1, 2]), 4, 0).is_none());
+    }
+}
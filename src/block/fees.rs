@@ -0,0 +1,201 @@
+pub const RATE_SCALE: usize = 1000;
+
+pub const MIN_FEE_RATE: usize = 1;
+
+pub const DEFAULT_TARGET: usize = 6;
+pub const MAX_TARGET: usize = 25;
+
+const MAX_SAMPLES: usize = 1000;
+const MIN_SAMPLES: usize = 10;
+
+const SUCCESS_RATIO: f64 = 0.85;
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    block::Block,
+    mempool::{MAX_BLOCK_SIZE, Mempool},
+    transaction::Transaction,
+};
+
+//fee rates are fee units per RATE_SCALE bytes
+pub fn fee_rate(fee: usize, size: usize) -> usize{
+    fee * RATE_SCALE / size.max(1)
+}
+
+pub fn fee_for_rate(rate: usize, size: usize) -> usize{
+    (rate * size).div_ceil(RATE_SCALE)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Tracked{
+    height: usize,
+    fee_rate: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Sample{
+    fee_rate: usize,
+    blocks: usize,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct FeeEstimate{
+    pub target: usize,
+    pub fee_rate: usize,
+}
+
+//learns how many blocks transactions at each fee rate waited before confirming
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FeeEstimator{
+    tracked: HashMap<String, Tracked>,
+    samples: VecDeque<Sample>,
+}
+
+impl FeeEstimator{
+    pub fn track(&mut self, transaction: &Transaction, fee: usize, height: usize){
+        self.tracked.entry(hex::encode(transaction.get_hash())).or_insert(Tracked {
+            height,
+            fee_rate: fee_rate(fee, transaction.get_size())
+        });
+    }
+
+    //evicted, expired or replaced without confirming, which says nothing about how long the rate waits
+    pub fn untrack(&mut self, transactions: &[Transaction]){
+        for transaction in transactions{
+            self.tracked.remove(&hex::encode(transaction.get_hash()));
+        }
+    }
+
+    pub fn add_block(&mut self, block: &Block){
+        let height = block.get_height();
+
+        for transaction in block.get_relayed_transactions(){
+            if let Some(tracked) = self.tracked.remove(&hex::encode(transaction.get_hash())){
+                self.push_sample(tracked.fee_rate, height.saturating_sub(tracked.height) + 1);
+            }
+        }
+
+        //anything waiting longer than the largest target counts as a miss for every target
+        let expired: Vec<String> = self.tracked.iter()
+            .filter(|(_txid, tracked)| height.saturating_sub(tracked.height) >= MAX_TARGET)
+            .map(|(txid, _tracked)| txid.clone())
+            .collect();
+        for txid in expired{
+            if let Some(tracked) = self.tracked.remove(&txid){
+                self.push_sample(tracked.fee_rate, MAX_TARGET + 1);
+            }
+        }
+    }
+
+    fn push_sample(&mut self, fee_rate: usize, blocks: usize){
+        self.samples.push_back(Sample { fee_rate, blocks });
+        if self.samples.len() > MAX_SAMPLES{
+            self.samples.pop_front();
+        }
+    }
+
+    //lowest rate at which enough past transactions confirmed within the target
+    fn estimate_from_history(&self, target: usize) -> Option<usize>{
+        let mut samples: Vec<&Sample> = self.samples.iter().collect();
+        samples.sort_by_key(|sample| Reverse(sample.fee_rate));
+
+        let mut estimate = None;
+        let mut confirmed = 0;
+        for (index, sample) in samples.iter().enumerate(){
+            if sample.blocks <= target{
+                confirmed += 1;
+            }
+            //only judge a rate once every sample at that rate is counted
+            if samples.get(index + 1).is_some_and(|next| next.fee_rate == sample.fee_rate){
+                continue
+            }
+            let total = index + 1;
+            if total >= MIN_SAMPLES && confirmed as f64 >= total as f64 * SUCCESS_RATIO{
+                estimate = Some(sample.fee_rate);
+            }
+        }
+        estimate
+    }
+
+    //rate needed to outbid the transactions already waiting for the next target blocks
+    fn estimate_from_mempool(&self, target: usize, mempool: &Mempool) -> usize{
+        match mempool.get_fee_rate_at(MAX_BLOCK_SIZE * target){
+            Some(rate) => rate + 1,
+            None => MIN_FEE_RATE,
+        }
+    }
+
+    pub fn estimate(&self, target: usize, mempool: &Mempool) -> FeeEstimate{
+        let target = target.clamp(1, MAX_TARGET);
+        let fee_rate = self.estimate_from_history(target)
+            .unwrap_or(MIN_FEE_RATE)
+            .max(self.estimate_from_mempool(target, mempool))
+            .max(MIN_FEE_RATE);
+
+        FeeEstimate { target, fee_rate }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::block::keys::PrivateKey;
+
+    #[test]
+    fn history_estimate_prefers_rates_that_confirmed(){
+        let mut estimator = FeeEstimator::default();
+        for _ in 0..20{
+            estimator.push_sample(50, 1);
+            estimator.push_sample(10, 4);
+            estimator.push_sample(2, MAX_TARGET + 1);
+        }
+
+        assert_eq!(estimator.estimate_from_history(1), Some(50));
+        assert_eq!(estimator.estimate_from_history(6), Some(10));
+        assert_eq!(estimator.estimate(6, &Mempool::new()).fee_rate, 10);
+    }
+
+    #[test]
+    fn fee_for_rate_rounds_up(){
+        assert_eq!(fee_for_rate(10, 250), 3);
+        assert_eq!(fee_for_rate(10, 100), 1);
+        assert!(fee_rate(fee_for_rate(7, 333), 333) >= 7);
+    }
+
+    #[test]
+    fn full_mempool_raises_the_estimate_above_history(){
+        let mut estimator = FeeEstimator::default();
+        for _ in 0..20{
+            estimator.push_sample(10, 1);
+        }
+
+        let mut mempool = Mempool::new();
+        let mut value = 0;
+        while mempool.get_bytes() <= MAX_BLOCK_SIZE{
+            let transaction = Transaction::reward(value, PrivateKey::new().get_public_key(), 0);
+            let fee = fee_for_rate(100, transaction.get_size());
+            mempool.add_transaction(transaction, fee);
+            value += 1;
+        }
+
+        let mempool_rate = estimator.estimate_from_mempool(1, &mempool);
+        assert!(mempool_rate > 10);
+        assert_eq!(estimator.estimate(1, &mempool).fee_rate, mempool_rate);
+        assert_eq!(estimator.estimate(1, &Mempool::new()).fee_rate, 10);
+    }
+
+    #[test]
+    fn untracked_transactions_are_not_misses(){
+        let mut estimator = FeeEstimator::default();
+        let transaction = Transaction::reward(1, PrivateKey::new().get_public_key(), 0);
+        estimator.track(&transaction, 100, 0);
+        estimator.untrack(&[transaction]);
+        assert!(estimator.tracked.is_empty());
+    }
+}
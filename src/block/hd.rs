@@ -0,0 +1,232 @@
+pub const HARDENED: u32 = 0x8000_0000;
+
+const GAP_LIMIT: u32 = 20;
+
+const MASTER_KEY: &[u8] = b"Bitcoin seed";
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+
+use hmac::{Hmac, Mac};
+
+use k256::{FieldBytes, ProjectivePoint, Scalar, elliptic_curve::PrimeField};
+
+use serde::{Deserialize, Serialize};
+
+use sha2::Sha512;
+
+use super::keys::{PrivateKey, PublicKey};
+
+type HmacSha512 = Hmac<Sha512>;
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> (Vec<u8>, Vec<u8>){
+    let mut mac = HmacSha512::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    let result = mac.finalize().into_bytes();
+    (result[..32].to_vec(), result[32..].to_vec())
+}
+
+fn parse_scalar(bytes: &[u8]) -> Result<Scalar>{
+    let bytes: [u8; 32] = bytes.try_into()?;
+    Option::from(Scalar::from_repr(FieldBytes::from(bytes)))
+        .ok_or(anyhow!("Derived key is out of range"))
+}
+
+#[derive(Clone, Debug)]
+pub struct ExtendedPrivateKey{
+    key: PrivateKey,
+    chain_code: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExtendedPublicKey{
+    key: PublicKey,
+    chain_code: Vec<u8>,
+}
+
+impl ExtendedPrivateKey{
+    pub fn from_seed(seed: &[u8]) -> Result<Self>{
+        let (key, chain_code) = hmac_sha512(MASTER_KEY, seed);
+        Ok(Self {
+            key: PrivateKey::from_slice(&key)?,
+            chain_code
+        })
+    }
+
+    pub fn derive_child(&self, index: u32) -> Result<Self>{
+        let mut data = if index >= HARDENED{
+            [vec![0], self.key.to_bytes()].concat()
+        }else{
+            self.key.get_public_key().to_vec()
+        };
+        data.extend(index.to_be_bytes());
+
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+        let scalar = parse_scalar(&tweak)? + self.key.to_scalar();
+
+        Ok(Self {
+            key: PrivateKey::from_scalar(scalar)?,
+            chain_code
+        })
+    }
+
+    pub fn derive_path(&self, path: &[u32]) -> Result<Self>{
+        path.iter().try_fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    pub fn get_private_key(&self) -> PrivateKey{
+        self.key.clone()
+    }
+
+    pub fn to_extended_public_key(&self) -> ExtendedPublicKey{
+        ExtendedPublicKey {
+            key: self.key.get_public_key(),
+            chain_code: self.chain_code.clone()
+        }
+    }
+}
+
+impl ExtendedPublicKey{
+    pub fn derive_child(&self, index: u32) -> Result<Self>{
+        if index >= HARDENED{
+            return Err(anyhow!("Cannot derive a hardened child from a public key"))
+        }
+        let mut data = self.key.to_vec();
+        data.extend(index.to_be_bytes());
+
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+        let point = ProjectivePoint::GENERATOR * parse_scalar(&tweak)? + self.key.to_point();
+
+        Ok(Self {
+            key: PublicKey::from_point(point)?,
+            chain_code
+        })
+    }
+
+    pub fn get_public_key(&self) -> PublicKey{
+        self.key.clone()
+    }
+
+    fn to_hex(&self) -> String{
+        hex::encode([self.chain_code.clone(), self.key.to_vec()].concat())
+    }
+
+    fn from_hex(data: String) -> Result<Self>{
+        let bytes = hex::decode(data)?;
+        if bytes.len() <= 32{
+            return Err(anyhow!("Extended public key too short"))
+        }
+        Ok(Self {
+            key: PublicKey::from_bytes(bytes[32..].to_vec())?,
+            chain_code: bytes[..32].to_vec()
+        })
+    }
+}
+
+impl Serialize for ExtendedPublicKey{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer {
+        self.to_hex().serialize(serializer)
+    }
+}
+
+impl <'de>Deserialize<'de> for ExtendedPublicKey{
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de> {
+        let data = String::deserialize(deserializer)?;
+        use serde::de::Error;
+        ExtendedPublicKey::from_hex(data).map_err(D::Error::custom)
+    }
+}
+
+//public keys of one derivation chain, kept GAP_LIMIT keys ahead of the first unused one
+#[derive(Clone, Debug)]
+pub struct KeyChain{
+    xpub: ExtendedPublicKey,
+    keys: Vec<PublicKey>,
+    lookup: HashMap<Vec<u8>, u32>,
+    next_index: u32,
+}
+
+impl KeyChain{
+    pub fn new(xpub: ExtendedPublicKey, next_index: u32) -> Self{
+        let mut chain = Self {
+            xpub,
+            keys: Vec::new(),
+            lookup: HashMap::new(),
+            next_index
+        };
+        chain.fill_lookahead();
+        chain
+    }
+
+    fn fill_lookahead(&mut self){
+        while (self.keys.len() as u32) < self.next_index + GAP_LIMIT{
+            let index = self.keys.len() as u32;
+            //an invalid child has probability below 2^-127
+            let key = self.xpub.derive_child(index)
+                .expect("Unable to derive child key")
+                .get_public_key();
+            self.lookup.insert(key.to_hash(), index);
+            self.keys.push(key);
+        }
+    }
+
+    pub fn get_index(&self, public_key_hash: &Vec<u8>) -> Option<u32>{
+        self.lookup.get(public_key_hash).copied()
+    }
+
+    pub fn mark_used(&mut self, index: u32){
+        if index >= self.next_index{
+            self.next_index = index + 1;
+            self.fill_lookahead();
+        }
+    }
+
+    pub fn current_key(&self) -> PublicKey{
+        self.keys[self.next_index as usize].clone()
+    }
+
+    pub fn next_key(&mut self) -> PublicKey{
+        let key = self.current_key();
+        self.mark_used(self.next_index);
+        key
+    }
+
+    pub fn get_next_index(&self) -> u32{
+        self.next_index
+    }
+
+    pub fn get_xpub(&self) -> ExtendedPublicKey{
+        self.xpub.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn bip32_test_vector(){
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivateKey::from_seed(&seed).unwrap();
+        let child = master.derive_path(&[HARDENED, 1]).unwrap();
+
+        assert_eq!(
+            hex::encode(child.get_private_key().to_bytes()),
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"
+        );
+
+        let public_child = master.derive_child(HARDENED).unwrap()
+            .to_extended_public_key()
+            .derive_child(1).unwrap();
+
+        assert_eq!(
+            public_child.get_public_key().to_vec(),
+            child.get_private_key().get_public_key().to_vec()
+        );
+    }
+}
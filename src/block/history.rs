@@ -0,0 +1,119 @@
+use chrono::DateTime;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction{
+    Received,
+    Sent,
+    SelfTransfer,
+    Mined,
+}
+
+impl Direction{
+    fn as_str(&self) -> &'static str{
+        match self{
+            Direction::Received => "received",
+            Direction::Sent => "sent",
+            Direction::SelfTransfer => "self_transfer",
+            Direction::Mined => "mined",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntry{
+    pub txid: String,
+    pub direction: Direction,
+    pub amount: usize,
+    pub fee: usize,
+    pub counterparties: Vec<String>,
+    pub height: usize,
+    pub timestamp: usize,
+    pub label: Option<String>,
+    #[serde(default)]
+    pub watch_only: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryView{
+    #[serde(flatten)]
+    pub entry: HistoryEntry,
+    pub confirmations: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct History(Vec<HistoryEntry>);
+
+impl History{
+    pub fn push(&mut self, entry: HistoryEntry){
+        self.0.push(entry);
+    }
+
+    pub fn remove_from(&mut self, height: usize){
+        self.0.retain(|entry| entry.height < height);
+    }
+
+    pub fn get_labels(&self) -> Vec<(String, String)>{
+        self.0.iter()
+            .filter_map(|entry| entry.label.clone().map(|label| (entry.txid.clone(), label)))
+            .collect()
+    }
+
+    pub fn set_label(&mut self, txid: &str, label: Option<String>) -> bool{
+        let mut found = false;
+        for entry in self.0.iter_mut().filter(|entry| entry.txid == txid){
+            entry.label = label.clone();
+            found = true;
+        }
+        found
+    }
+
+    pub fn get_views(&self, tip_height: Option<usize>) -> Vec<HistoryView>{
+        self.0.iter().map(|entry| HistoryView {
+            entry: entry.clone(),
+            confirmations: match tip_height{
+                Some(tip) if tip >= entry.height => tip - entry.height + 1,
+                _ => 0,
+            }
+        }).collect()
+    }
+
+    pub fn to_csv(&self, tip_height: Option<usize>) -> String{
+        let mut csv = String::from(
+            "txid,direction,amount,fee,counterparties,height,confirmations,timestamp,date,label,watch_only\n"
+        );
+
+        for view in self.get_views(tip_height){
+            let entry = view.entry;
+            let date = DateTime::from_timestamp(entry.timestamp as i64, 0)
+                .map(|date| date.to_rfc3339())
+                .unwrap_or_default();
+
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{},{},{}\n",
+                entry.txid,
+                entry.direction.as_str(),
+                entry.amount,
+                entry.fee,
+                entry.counterparties.join(";"),
+                entry.height,
+                view.confirmations,
+                entry.timestamp,
+                date,
+                escape_csv(&entry.label.unwrap_or_default()),
+                entry.watch_only,
+            ));
+        }
+        csv
+    }
+}
+
+fn escape_csv(field: &str) -> String{
+    if field.contains([',', '"', '\n']){
+        format!("\"{}\"", field.replace('"', "\"\""))
+    }else{
+        field.to_string()
+    }
+}
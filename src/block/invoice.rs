@@ -0,0 +1,323 @@
+const URI_SCHEME: &str = "compcoin";
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::address::{Address, AddressError};
+
+//compcoin:<address>?amount=<value>&label=<text>&message=<text>&expires=<unix time>
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PaymentUri{
+    pub address: Address,
+    pub amount: Option<usize>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    pub expires: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UriError{
+    InvalidScheme,
+    InvalidAddress(AddressError),
+    InvalidAmount(String),
+    InvalidExpiry(String),
+    InvalidEncoding(String),
+    UnknownRequirement(String),
+}
+
+impl fmt::Display for UriError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            UriError::InvalidScheme => write!(f, "Payment request must start with '{}:'", URI_SCHEME),
+            UriError::InvalidAddress(e) => write!(f, "Payment request address is not valid: {}", e),
+            UriError::InvalidAmount(amount) => write!(f, "Payment request amount is not valid: {}", amount),
+            UriError::InvalidExpiry(expiry) => write!(f, "Payment request expiry is not valid: {}", expiry),
+            UriError::InvalidEncoding(e) => write!(f, "Payment request is not encoded correctly: {}", e),
+            UriError::UnknownRequirement(key) => write!(f, "Payment request requires unsupported parameter: {}", key),
+        }
+    }
+}
+
+impl std::error::Error for UriError{}
+
+impl PaymentUri{
+    pub fn is_expired(&self, now: usize) -> bool{
+        self.expires.is_some_and(|expires| now >= expires)
+    }
+}
+
+fn encode(text: &str) -> String{
+    let mut encoded = String::new();
+    for byte in text.bytes(){
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte){
+            encoded.push(byte as char);
+        }else{
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn decode(text: &str) -> Result<String, UriError>{
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut index = 0;
+    while index < bytes.len(){
+        match bytes[index]{
+            b'%' => {
+                let hex = text.get(index + 1..index + 3).ok_or(UriError::InvalidEncoding(text.to_string()))?;
+                decoded.push(u8::from_str_radix(hex, 16).map_err(|_| UriError::InvalidEncoding(text.to_string()))?);
+                index += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|e| UriError::InvalidEncoding(e.to_string()))
+}
+
+impl fmt::Display for PaymentUri{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut params = Vec::new();
+        if let Some(amount) = self.amount{
+            params.push(format!("amount={}", amount));
+        }
+        if let Some(label) = &self.label{
+            params.push(format!("label={}", encode(label)));
+        }
+        if let Some(message) = &self.message{
+            params.push(format!("message={}", encode(message)));
+        }
+        if let Some(expires) = self.expires{
+            params.push(format!("expires={}", expires));
+        }
+
+        write!(f, "{}:{}", URI_SCHEME, self.address)?;
+        if !params.is_empty(){
+            write!(f, "?{}", params.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentUri{
+    type Err = UriError;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        let data = data.trim();
+        let Some((scheme, rest)) = data.split_once(':') else{
+            return Err(UriError::InvalidScheme)
+        };
+        if !scheme.eq_ignore_ascii_case(URI_SCHEME){
+            return Err(UriError::InvalidScheme)
+        }
+
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut uri = PaymentUri {
+            address: address.parse().map_err(UriError::InvalidAddress)?,
+            amount: None,
+            label: None,
+            message: None,
+            expires: None,
+        };
+
+        for param in query.split('&').filter(|param| !param.is_empty()){
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            match key{
+                "amount" => uri.amount = Some(value.parse().map_err(|_| UriError::InvalidAmount(value.to_string()))?),
+                "label" => uri.label = Some(decode(value)?),
+                "message" => uri.message = Some(decode(value)?),
+                "expires" => uri.expires = Some(value.parse().map_err(|_| UriError::InvalidExpiry(value.to_string()))?),
+                //unknown parameters are ignored unless the sender marked them as required
+                key if key.starts_with("req-") => return Err(UriError::UnknownRequirement(key.to_string())),
+                _ => {}
+            }
+        }
+        Ok(uri)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InvoicePayment{
+    pub txid: String,
+    #[serde(default)]
+    pub output_index: usize,
+    pub value: usize,
+    pub height: usize,
+}
+
+//a payment request tied to a fresh receive address, paid once confirmed outputs to it reach the amount
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Invoice{
+    pub id: String,
+    pub address: Address,
+    pub amount: Option<usize>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    pub created: usize,
+    pub expires: Option<usize>,
+    pub payments: Vec<InvoicePayment>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceStatus{
+    Unpaid,
+    Paid,
+    Expired,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InvoiceView{
+    #[serde(flatten)]
+    pub invoice: Invoice,
+    pub uri: String,
+    pub received: usize,
+    pub status: InvoiceStatus,
+}
+
+impl Invoice{
+    pub fn get_uri(&self) -> PaymentUri{
+        PaymentUri {
+            address: self.address.clone(),
+            amount: self.amount,
+            label: self.label.clone(),
+            message: self.message.clone(),
+            expires: self.expires,
+        }
+    }
+
+    pub fn get_received(&self) -> usize{
+        self.payments.iter().map(|payment| payment.value).sum()
+    }
+
+    //a payment that confirms after expiry still counts, the funds were received
+    pub fn get_status(&self, now: usize) -> InvoiceStatus{
+        let received = self.get_received();
+        if received > 0 && received >= self.amount.unwrap_or(0){
+            InvoiceStatus::Paid
+        }else if self.get_uri().is_expired(now){
+            InvoiceStatus::Expired
+        }else{
+            InvoiceStatus::Unpaid
+        }
+    }
+
+    pub fn to_view(&self, now: usize) -> InvoiceView{
+        InvoiceView {
+            invoice: self.clone(),
+            uri: self.get_uri().to_string(),
+            received: self.get_received(),
+            status: self.get_status(now),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Invoices(Vec<Invoice>);
+
+impl Invoices{
+    pub fn push(&mut self, invoice: Invoice){
+        self.0.push(invoice);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Invoice>{
+        self.0.iter().find(|invoice| invoice.id == id)
+    }
+
+    pub fn get_views(&self, now: usize) -> Vec<InvoiceView>{
+        self.0.iter().map(|invoice| invoice.to_view(now)).collect()
+    }
+
+    //a transaction is only counted once per invoice, so replaying a block is harmless
+    //one transaction may pay the same invoice in several outputs, each is counted once
+    pub fn record_payment(&mut self, public_key_hash: &Vec<u8>, txid: &str, output_index: usize, value: usize, height: usize){
+        for invoice in self.0.iter_mut().filter(|invoice| &invoice.address.get_public_key_hash() == public_key_hash){
+            if !invoice.payments.iter().any(|payment| payment.txid == txid && payment.output_index == output_index){
+                invoice.payments.push(InvoicePayment { txid: txid.to_string(), output_index, value, height });
+            }
+        }
+    }
+
+    pub fn remove_from(&mut self, height: usize){
+        for invoice in self.0.iter_mut(){
+            invoice.payments.retain(|payment| payment.height < height);
+        }
+    }
+
+    //keeps invoices created while the rescan ran, taking payments from the rescanned copy
+    pub fn merge_payments(&mut self, rescanned: Invoices){
+        for rescanned in rescanned.0{
+            if let Some(invoice) = self.0.iter_mut().find(|invoice| invoice.id == rescanned.id){
+                invoice.payments = rescanned.payments;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::block::keys::PrivateKey;
+
+    #[test]
+    fn uri_round_trip(){
+        let address = PrivateKey::new().get_public_key().to_address();
+        let uri = PaymentUri {
+            address: address.clone(),
+            amount: Some(250),
+            label: Some("Coffee & cake".to_string()),
+            message: Some("order #12".to_string()),
+            expires: Some(1_700_000_000),
+        };
+        let encoded = uri.to_string();
+        assert!(encoded.starts_with(&format!("compcoin:{}?amount=250&label=Coffee%20%26%20cake", address)));
+        assert_eq!(encoded.parse::<PaymentUri>().unwrap(), uri);
+
+        let bare: PaymentUri = format!("compcoin:{}", address).parse().unwrap();
+        assert_eq!(bare.amount, None);
+        assert!(format!("compcoin:{}?foo=1", address).parse::<PaymentUri>().is_ok());
+        assert_eq!(
+            format!("compcoin:{}?req-foo=1", address).parse::<PaymentUri>().unwrap_err(),
+            UriError::UnknownRequirement("req-foo".to_string())
+        );
+        assert_eq!(address.to_string().parse::<PaymentUri>().unwrap_err(), UriError::InvalidScheme);
+    }
+
+    #[test]
+    fn invoice_paid_once_amount_confirms(){
+        let address = PrivateKey::new().get_public_key().to_address();
+        let mut invoices = Invoices::default();
+        invoices.push(Invoice {
+            id: "a".to_string(),
+            address: address.clone(),
+            amount: Some(100),
+            label: None,
+            message: None,
+            created: 0,
+            expires: Some(50),
+            payments: Vec::new(),
+        });
+        let hash = address.get_public_key_hash();
+
+        assert_eq!(invoices.get("a").unwrap().get_status(10), InvoiceStatus::Unpaid);
+        assert_eq!(invoices.get("a").unwrap().get_status(60), InvoiceStatus::Expired);
+
+        invoices.record_payment(&hash, "tx1", 0, 30, 5);
+        invoices.record_payment(&hash, "tx1", 0, 30, 5);
+        invoices.record_payment(&hash, "tx1", 1, 30, 5);
+        assert_eq!(invoices.get("a").unwrap().get_received(), 60);
+        invoices.record_payment(&hash, "tx2", 0, 40, 6);
+        assert_eq!(invoices.get("a").unwrap().get_status(60), InvoiceStatus::Paid);
+
+        invoices.remove_from(6);
+        assert_eq!(invoices.get("a").unwrap().get_status(10), InvoiceStatus::Unpaid);
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::info;
+
+use super::Block;
+
+use crate::utils::{format_number, generate_nonce, get_timestamp};
+
+//a block to mine, numbered so solutions can be traced back to the work they came from
+#[derive(Clone, Debug)]
+pub struct MiningJob{
+    pub id: u64,
+    pub template: Block,
+    //leading zero bytes a solution needs
+    pub difficulty: usize,
+    pub created: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Solution{
+    pub job_id: u64,
+    pub block: Block,
+}
+
+impl MiningJob{
+    pub fn new(id: u64, template: Block) -> Self{
+        Self {
+            id,
+            difficulty: template.get_difficulty(),
+            template,
+            created: get_timestamp()
+        }
+    }
+
+    pub fn get_height(&self) -> usize{
+        self.template.get_height()
+    }
+
+    pub fn get_prev_hash(&self) -> Vec<u8>{
+        self.template.get_prev_hash()
+    }
+
+    //once the tip moves nothing found for this job can be added
+    pub fn is_stale(&self, tip_hash: &[u8], next_height: usize) -> bool{
+        self.get_prev_hash() != tip_hash || self.get_height() != next_height
+    }
+
+    pub fn get_age(&self, now: usize) -> usize{
+        now.saturating_sub(self.created)
+    }
+
+    //hashes until a solution is found or the job is stopped, a solution stops every other worker too
+    pub fn mine(&self, stop: &AtomicBool, worker: usize) -> Option<Solution>{
+        info!("Thread: {} started mining job {}", worker, self.id);
+
+        let mut block = self.template.clone();
+        let mut count: usize = 1;
+
+        while !stop.load(Ordering::Relaxed){
+            block.set_nonce(generate_nonce());
+            if block.meets_difficulty(self.difficulty){
+                if stop.swap(true, Ordering::Relaxed){
+                    return None
+                }
+                info!("Mined block: {} for job {}", block.get_height(), self.id);
+                return Some(Solution { job_id: self.id, block })
+            }
+
+            if count.is_multiple_of(250_000) && worker == 0{
+                info!("Each thread tried: {} blocks", format_number(count));
+            }
+            count += 1;
+        }
+        None
+    }
+}
+
+impl Solution{
+    pub fn is_stale(&self, tip_hash: &[u8], next_height: usize) -> bool{
+        self.block.get_prev_hash() != tip_hash || self.block.get_height() != next_height
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn first_solution_stops_the_job(){
+        let job = MiningJob::new(3, Block::new(4, 1, 0, Vec::new(), vec![1; 32]));
+        let stop = AtomicBool::new(false);
+
+        let solution = job.mine(&stop, 0).unwrap();
+        assert_eq!(solution.job_id, 3);
+        assert_eq!(solution.block.check_header(), Ok(()));
+        assert!(stop.load(Ordering::Relaxed));
+        assert!(job.mine(&stop, 1).is_none());
+
+        assert!(!solution.is_stale(&[1; 32], 4));
+        assert!(solution.is_stale(&[2; 32], 4));
+        assert!(job.is_stale(&[1; 32], 5));
+    }
+}
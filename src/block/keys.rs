@@ -1,9 +1,14 @@
-use k256::ecdsa::{Signature, SigningKey, VerifyingKey, signature::{Verifier, Signer}};
+use k256::{
+    ProjectivePoint, Scalar,
+    ecdsa::{Signature, SigningKey, VerifyingKey, signature::{Verifier, Signer}},
+};
 
 use anyhow::Result;
 use rand_core::OsRng;
 use serde::{Deserialize, Serialize};
 
+use super::address::Address;
+
 use crate::utils::sha256;
 
 #[derive(Clone, Debug)]
@@ -26,11 +31,27 @@ impl PrivateKey{
         sig.to_vec()
     }
 
-    fn to_hex(&self) -> String{
+    pub fn to_hex(&self) -> String{
         hex::encode(self.0.to_bytes())
     }
 
-    fn from_hex(data: String) -> Result<Self>{
+    pub(super) fn from_slice(bytes: &[u8]) -> Result<Self>{
+        Ok(PrivateKey(SigningKey::from_slice(bytes)?))
+    }
+
+    pub(super) fn from_scalar(scalar: Scalar) -> Result<Self>{
+        Ok(PrivateKey(SigningKey::from_bytes(&scalar.to_bytes())?))
+    }
+
+    pub(super) fn to_scalar(&self) -> Scalar{
+        *self.0.as_nonzero_scalar().as_ref()
+    }
+
+    pub(super) fn to_bytes(&self) -> Vec<u8>{
+        self.0.to_bytes().to_vec()
+    }
+
+    pub fn from_hex(data: String) -> Result<Self>{
         let bytes = hex::decode(data)?;
         Ok(PrivateKey(SigningKey::from_slice(&bytes)?))
     }
@@ -41,6 +62,14 @@ impl PublicKey{
         Ok(Self(VerifyingKey::from_sec1_bytes(&bytes)?))
     }
 
+    pub(super) fn from_point(point: ProjectivePoint) -> Result<Self>{
+        Ok(Self(VerifyingKey::from_affine(point.to_affine())?))
+    }
+
+    pub(super) fn to_point(&self) -> ProjectivePoint{
+        ProjectivePoint::from(*self.0.as_affine())
+    }
+
     pub fn verify_sig(&self, sig_hash: Vec<u8>, signature: Vec<u8>) -> bool{
         let Ok(signature) = Signature::from_slice(&signature)else{
             return false
@@ -60,7 +89,11 @@ impl PublicKey{
         hex::encode(self.to_vec())
     }
 
-    fn from_hex(data: String) -> Result<Self>{
+    pub fn to_address(&self) -> Address{
+        Address::from(self)
+    }
+
+    pub fn from_hex(data: String) -> Result<Self>{
             PublicKey::from_bytes(hex::decode(data)?)
     }
 }
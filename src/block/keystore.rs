@@ -0,0 +1,180 @@
+const FILE_PATH: &str = "configs/wallet.keys";
+const WALLET_DIR: &str = "configs/wallets";
+
+const KEYRING_SERVICE: &str = "COMP-COIN";
+const KEYRING_USER: &str = "wallet";
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::{
+    Aes256Gcm, Nonce,
+    aead::{Aead, KeyInit},
+};
+
+use anyhow::{Result, anyhow};
+
+use argon2::Argon2;
+
+#[allow(unused_imports)]
+use log::{info, warn};
+
+use rand::RngCore;
+
+use serde::{Deserialize, Serialize};
+
+use super::wallet::DEFAULT_WALLET;
+
+#[derive(Serialize, Deserialize)]
+pub struct WalletSecrets{
+    pub mnemonic: String,
+    #[serde(default)]
+    pub imported_keys: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyFile{
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]>{
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Unable to derive key: {}", e))?;
+    Ok(key)
+}
+
+//the default wallet keeps the key file it had before wallets were named
+fn key_file_path(name: &str) -> PathBuf{
+    if name == DEFAULT_WALLET{
+        PathBuf::from(FILE_PATH)
+    }else{
+        PathBuf::from(WALLET_DIR).join(format!("{}.keys", name))
+    }
+}
+
+fn keyring_user(name: &str) -> String{
+    if name == DEFAULT_WALLET{
+        KEYRING_USER.to_string()
+    }else{
+        format!("{}-{}", KEYRING_USER, name)
+    }
+}
+
+fn read_key_file(name: &str) -> Result<KeyFile>{
+    let file = File::open(key_file_path(name))?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+fn decrypt(key_file: &KeyFile, key: &[u8; 32]) -> Result<WalletSecrets>{
+    let cipher = Aes256Gcm::new_from_slice(key)?;
+    let nonce: [u8; 12] = hex::decode(&key_file.nonce)?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid nonce in key file"))?;
+    let plaintext = cipher
+        .decrypt(&Nonce::from(nonce), hex::decode(&key_file.ciphertext)?.as_ref())
+        .map_err(|_| anyhow!("Incorrect passphrase"))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+fn write_key_file(name: &str, secrets: &WalletSecrets, key: &[u8; 32], salt: &[u8]) -> Result<()>{
+    let mut nonce = [0u8; 12];
+    rand::rng().fill_bytes(&mut nonce);
+
+    let cipher = Aes256Gcm::new_from_slice(key)?;
+    let ciphertext = cipher
+        .encrypt(&Nonce::from(nonce), serde_json::to_vec(secrets)?.as_ref())
+        .map_err(|_| anyhow!("Unable to encrypt wallet secrets"))?;
+
+    let key_file = KeyFile {
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    };
+    let path = key_file_path(name);
+    if let Some(dir) = path.parent(){
+        fs::create_dir_all(dir)?;
+    }
+    let file = create_private_file(&path)?;
+    serde_json::to_writer(file, &key_file)?;
+    Ok(())
+}
+
+//only the owner may read the key file
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> Result<File>{
+    use std::{fs::OpenOptions, os::unix::fs::{OpenOptionsExt, PermissionsExt}};
+
+    let file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    //mode only applies to new files, older key files are tightened too
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> Result<File>{
+    Ok(File::create(path)?)
+}
+
+pub fn save(name: &str, secrets: &WalletSecrets, passphrase: &str) -> Result<()>{
+    let mut salt = [0u8; 16];
+    rand::rng().fill_bytes(&mut salt);
+
+    let key = derive_key(passphrase, &salt)?;
+    write_key_file(name, secrets, &key, &salt)
+}
+
+//keeps the salt so a key remembered in the keyring stays valid
+pub fn update(name: &str, secrets: &WalletSecrets, key: &[u8; 32]) -> Result<()>{
+    let key_file = read_key_file(name)?;
+    write_key_file(name, secrets, key, &hex::decode(&key_file.salt)?)
+}
+
+//returns the derived key as well so it can be remembered in the keyring
+pub fn unlock(name: &str, passphrase: &str) -> Result<(WalletSecrets, [u8; 32])>{
+    let key_file = read_key_file(name)?;
+    let key = derive_key(passphrase, &hex::decode(&key_file.salt)?)?;
+    Ok((decrypt(&key_file, &key)?, key))
+}
+
+fn unlock_with_remembered_key(name: &str) -> Result<(WalletSecrets, [u8; 32])>{
+    let entry = keyring::Entry::new(KEYRING_SERVICE, &keyring_user(name))?;
+    let key: [u8; 32] = hex::decode(entry.get_password()?)?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid key stored in keyring"))?;
+    Ok((decrypt(&read_key_file(name)?, &key)?, key))
+}
+
+//no passphrase uses the key remembered in the OS keyring
+pub fn open(name: &str, passphrase: Option<&str>) -> Result<(WalletSecrets, [u8; 32])>{
+    match passphrase{
+        Some(passphrase) => unlock(name, passphrase),
+        None => unlock_with_remembered_key(name),
+    }
+}
+
+pub fn exists(name: &str) -> bool{
+    key_file_path(name).exists()
+}
+
+pub fn remember_key(name: &str, key: &[u8; 32]) -> Result<()>{
+    let entry = keyring::Entry::new(KEYRING_SERVICE, &keyring_user(name))?;
+    entry.set_password(&hex::encode(key))?;
+    info!("Stored wallet key in OS keyring");
+    Ok(())
+}
+
+pub fn forget_key(name: &str){
+    let result = keyring::Entry::new(KEYRING_SERVICE, &keyring_user(name))
+        .and_then(|entry| entry.delete_credential());
+    match result{
+        Ok(()) => info!("Removed wallet key from OS keyring"),
+        Err(keyring::Error::NoEntry) => {},
+        Err(e) => warn!("Unable to remove wallet key from OS keyring: {}", e),
+    }
+}
@@ -1,7 +1,20 @@
-const TRANSACTIONS_PER_BLOCK: usize = 10;
+//bytes of transactions per block, the coinbase included
+pub(super) const MAX_BLOCK_SIZE: usize = 4_000;
+
+pub const DEFAULT_MAX_MEMPOOL_SIZE: usize = 300_000;
+pub const DEFAULT_MEMPOOL_EXPIRY: usize = 72 * 60 * 60;
+
+//fee deltas for transactions not in the mempool are capped and expire like entries do
+const MAX_FEE_DELTAS: usize = 1_000;
+
+//the rolling minimum fee rate halves this often once the mempool stops evicting
+const MIN_FEE_HALF_LIFE: usize = 60 * 60;
+const INCREMENTAL_FEE_RATE: usize = MIN_FEE_RATE;
 
 use std::{
-    collections::{BinaryHeap, HashSet},
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt,
     hash::{Hash},
     sync::Arc,
 };
@@ -20,167 +33,447 @@ use super::{
     block::Block,
     utxos::UTXOS,
     keys::PublicKey,
+    fees::{MIN_FEE_RATE, fee_for_rate, fee_rate},
 };
 
+use crate::utils::get_timestamp;
+
+//max_size is in serialized bytes, max_age in seconds
+#[derive(Clone, Copy, Debug)]
+pub struct MempoolLimits{
+    pub max_size: usize,
+    pub max_age: usize,
+}
+
+impl Default for MempoolLimits{
+    fn default() -> Self{
+        Self { 
+            max_size: DEFAULT_MAX_MEMPOOL_SIZE, 
+            max_age: DEFAULT_MEMPOOL_EXPIRY 
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
-struct HeapSet<T>{
-    heap: BinaryHeap<T>,
-    elements: HashSet<T>,
+pub struct Mempool{
+    entries: HashMap<Vec<u8>, TransactionWithFee>,
+    //serialized size of every entry, kept up to date as entries come and go
+    bytes: usize,
+    added: HashMap<Vec<u8>, usize>,
+    //outpoint to the txid of the mempool transaction spending it
+    spent: HashMap<(Vec<u8>, usize), Vec<u8>>,
+    limits: MempoolLimits,
+    //rate and the time it was last raised by an eviction
+    min_fee: (usize, usize),
+    //operator fee adjustments by txid, only used to choose what gets mined
+    deltas: HashMap<Vec<u8>, FeeDelta>,
 }
 
-impl<T: Ord + Clone + Hash> HeapSet<T>{
-    fn new() -> Self{
+impl Mempool {
+    pub fn new() -> Self{
         Self{
-            heap: BinaryHeap::new(),
-            elements: HashSet::new(),
+            entries: HashMap::new(),
+            bytes: 0,
+            added: HashMap::new(),
+            spent: HashMap::new(),
+            limits: MempoolLimits::default(),
+            min_fee: (MIN_FEE_RATE, 0),
+            deltas: HashMap::new(),
         }
     }
 
-    fn push(&mut self, item: T){
-        if self.elements.insert(item.clone()) {
-            self.heap.push(item);
+    pub fn set_limits(&mut self, limits: MempoolLimits){
+        self.limits = limits;
+    }
+
+    pub fn contains(&self, transaction: &Transaction) -> bool{
+        self.entries.contains_key(&transaction.get_hash())
+    }
+
+    fn push(&mut self, tx: Transaction, fee: usize, added: usize){
+        let txid = tx.get_hash();
+        if self.entries.contains_key(&txid){
+            return
+        }
+        for input in tx.inputs.iter(){
+            self.spent.insert((input.prev.clone(), input.output_index), txid.clone());
         }
+        self.added.insert(txid.clone(), added);
+        let entry = TransactionWithFee::new(tx, fee);
+        self.bytes += entry.get_size();
+        self.entries.insert(txid, entry);
     }
 
-    fn pop(&mut self) -> Option<T>{
-        if let Some(item) = self.heap.pop(){
-            self.elements.remove(&item);
-            Some(item)
-        } else {
-            None
+    //loaded entries count as added now, so they get a full expiry period
+    fn from_vec(v: Vec<TransactionWithFee>) -> Result<Self>{
+        let now = get_timestamp();
+        let mut mempool = Self::new();
+        for entry in v{
+            mempool.push(entry.transaction, entry.fee, now);
         }
+        Ok(mempool)
     }
 
-    fn contains(&self, item: T) -> bool{
-        self.elements.contains(&item)
+    pub fn to_vec(&self) -> Vec<TransactionWithFee>{
+        self.entries.values().cloned().collect()
     }
 
-    fn get_vec(&self) -> Vec<T>{
-        self.heap.clone().into_vec()
+    //oldest first, so a reload settles conflicts the way they were settled when admitted
+    pub fn get_entries(&self) -> Vec<MempoolEntry>{
+        let mut entries: Vec<MempoolEntry> = self.to_vec().into_iter()
+            .map(|entry| {
+                let txid = entry.transaction.get_hash();
+                MempoolEntry { 
+                    transaction: entry.transaction, 
+                    added: self.added.get(&txid).copied().unwrap_or(0),
+                    fee_delta: self.get_fee_delta(&txid),
+                }
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.added);
+        entries
     }
 
-    fn from_vec(v: Vec<T>) -> Self{
-        Self { 
-            heap: BinaryHeap::from(v.clone()),
-            elements: HashSet::from_iter(v)
+    pub fn remove(&mut self, transactions: Vec<Transaction>){
+        for tx in transactions{
+            let txid = tx.get_hash();
+            let Some(entry) = self.entries.remove(&txid) else{
+                continue
+            };
+            for input in entry.transaction.inputs.iter(){
+                let outpoint = (input.prev.clone(), input.output_index);
+                if self.spent.get(&outpoint) == Some(&txid){
+                    self.spent.remove(&outpoint);
+                }
+            }
+            self.added.remove(&txid);
+            self.bytes -= entry.get_size();
         }
     }
 
-    fn remove(&mut self, items: HashSet<T>){
-        for item in items.iter(){
-            self.elements.remove(item);
+    //deltas add up and may be set before the transaction arrives
+    pub fn prioritise(&mut self, txid: Vec<u8>, delta: isize, now: usize){
+        let total = self.get_fee_delta(&txid) + delta;
+        if total == 0{
+            self.deltas.remove(&txid);
+        }else{
+            self.deltas.insert(txid, FeeDelta { delta: total, set: now });
         }
-        let vec: Vec<T> = self.heap.drain()
-            .filter(|x| !items.contains(x))
-            .collect();
-        self.heap = BinaryHeap::from(vec)
-    }
 
-    fn update(&mut self, other: Self){
-        for item in other.elements.iter(){
-            self.push(item.clone())
+        //drop the oldest deltas for transactions that never arrived
+        while self.deltas.len() > MAX_FEE_DELTAS{
+            let oldest = self.deltas.iter()
+                .filter(|(txid, _fee_delta)| !self.entries.contains_key(*txid))
+                .min_by_key(|(_txid, fee_delta)| fee_delta.set)
+                .map(|(txid, _fee_delta)| txid.clone());
+            let Some(oldest) = oldest else{
+                break
+            };
+            self.deltas.remove(&oldest);
         }
     }
 
-    fn size(&self) -> usize{
-        self.elements.len()
+    pub fn get_fee_delta(&self, txid: &Vec<u8>) -> isize{
+        self.deltas.get(txid).map_or(0, |fee_delta| fee_delta.delta)
     }
-}
 
+    //the fee the miner treats the transaction as paying, never below zero
+    pub fn get_modified_fee(&self, txid: &Vec<u8>, fee: usize) -> usize{
+        fee.saturating_add_signed(self.get_fee_delta(txid))
+    }
 
-#[derive(Clone, Debug)]
-pub struct Mempool(HeapSet<TransactionWithFee>);
+    pub fn get_bytes(&self) -> usize{
+        self.bytes
+    }
 
-impl Mempool {
-    pub fn new() -> Self{
-        Self(HeapSet::new())
+    //rises when the mempool is full, then decays back towards the static minimum
+    pub fn get_min_fee_rate(&self, now: usize) -> usize{
+        let (rate, raised) = self.min_fee;
+        let halvings = now.saturating_sub(raised) / MIN_FEE_HALF_LIFE;
+        rate.checked_shr(halvings as u32).unwrap_or(0).max(MIN_FEE_RATE)
     }
 
-    pub fn contains(&self, transaction: &Transaction) -> bool{
-        self.0.contains(TransactionWithFee::new(transaction.clone(), 0))
+    //evicts the lowest fee rate entries, with anything spending them, until the mempool fits
+    fn trim(&mut self, now: usize) -> Vec<Transaction>{
+        let mut evicted = Vec::new();
+
+        while self.bytes > self.limits.max_size{
+            let Some(lowest) = self.entries.values().min().cloned() else{
+                break
+            };
+            let rate = self.get_min_fee_rate(now).max(lowest.get_fee_rate() + INCREMENTAL_FEE_RATE);
+            self.min_fee = (rate, now);
+
+            let victims: Vec<Transaction> = self.with_descendants(vec![lowest]).into_iter()
+                .map(|entry| entry.transaction)
+                .collect();
+            self.remove(victims.clone());
+            evicted.extend(victims);
+        }
+        evicted
     }
 
-    fn push(&mut self, tx: Transaction, fee: usize){
-        self.0.push(TransactionWithFee { transaction: tx, fee });
+    //after the limits change or entries are reloaded
+    pub fn enforce_limits(&mut self, now: usize) -> Vec<Transaction>{
+        let mut removed = self.expire(now);
+        removed.extend(self.trim(now));
+        removed
     }
 
-    fn from_vec(v: Vec<TransactionWithFee>) -> Result<Self>{
-        Ok(Self(HeapSet::from_vec(v)))
+    pub fn expire(&mut self, now: usize) -> Vec<Transaction>{
+        let entries = &self.entries;
+        let max_age = self.limits.max_age;
+        self.deltas.retain(|txid, fee_delta| entries.contains_key(txid) || now.saturating_sub(fee_delta.set) < max_age);
+
+        let old: Vec<TransactionWithFee> = self.entries.iter()
+            .filter(|(txid, _entry)| self.added.get(*txid)
+                .is_some_and(|added| now.saturating_sub(*added) >= self.limits.max_age))
+            .map(|(_txid, entry)| entry.clone())
+            .collect();
+        let expired: Vec<Transaction> = self.with_descendants(old).into_iter()
+            .map(|entry| entry.transaction)
+            .collect();
+        self.remove(expired.clone());
+        expired
     }
 
-    fn pop(&mut self) -> Option<TransactionWithFee>{
-        self.0.pop()
+    pub fn get(&self, txid: &Vec<u8>) -> Option<TransactionWithFee>{
+        self.entries.get(txid).cloned()
     }
 
-    pub fn to_vec(&self) -> Vec<TransactionWithFee>{
-        self.0.get_vec()
+    pub fn get_spender(&self, hash: &[u8], index: usize) -> Option<TransactionWithFee>{
+        self.spent.get(&(hash.to_vec(), index)).and_then(|txid| self.get(txid))
     }
 
-    fn remove(&mut self, transactions: Vec<Transaction>){
-        let mut set = HashSet::new();
-        for mut tx in transactions{
-            tx.remove_fee();
-            set.insert(TransactionWithFee::new(tx, 0));
+    //mempool transactions spending any of the outpoints, each listed once
+    fn get_conflicts(&self, tx: &Transaction) -> Vec<TransactionWithFee>{
+        let mut conflicts: Vec<TransactionWithFee> = Vec::new();
+        for input in tx.inputs.iter(){
+            if let Some(entry) = self.get_spender(&input.prev, input.output_index)
+                && !conflicts.contains(&entry){
+                conflicts.push(entry);
+            }
         }
-        self.0.remove(set);
+        conflicts
     }
 
-    pub fn add_block(&mut self, block: &Block){
-        self.remove(block.get_transactions());
+    //the given entries plus everything in the mempool that spends their outputs
+    fn with_descendants(&self, entries: Vec<TransactionWithFee>) -> Vec<TransactionWithFee>{
+        let mut result = entries;
+        let mut index = 0;
+
+        while index < result.len(){
+            let transaction = &result[index].transaction;
+            let txid = transaction.get_hash();
+            let children: Vec<TransactionWithFee> = (0..transaction.outputs.len())
+                .filter_map(|output_index| self.get_spender(&txid, output_index))
+                .collect();
+            for child in children{
+                if !result.contains(&child){
+                    result.push(child);
+                }
+            }
+            index += 1;
+        }
+        result
+    }
+
+    //policy and consensus checks for a new transaction, inputs must be confirmed
+    pub fn check_transaction(&self, tx: &Transaction, utxos: &UTXOS) -> Result<Admission, MempoolError>{
+        if self.contains(tx){
+            return Err(MempoolError::AlreadyKnown)
+        }
+        if tx.inputs.is_empty(){
+            return Err(MempoolError::MissingInputs)
+        }
+        if UTXOS::has_duplicate_inputs(tx){
+            return Err(MempoolError::DuplicateInputs)
+        }
+
+        let conflicts = self.get_conflicts(tx);
+        let replaced = self.with_descendants(conflicts.clone());
+        let replaced_txids: HashSet<Vec<u8>> = replaced.iter()
+            .map(|entry| entry.transaction.get_hash())
+            .collect();
+
+        let mut prevouts = Vec::new();
+        for input in tx.inputs.iter(){
+            if replaced_txids.contains(&input.prev){
+                return Err(MempoolError::SpendsConflict)
+            }
+            if self.get(&input.prev).is_some(){
+                return Err(MempoolError::SpendsUnconfirmed)
+            }
+            let Some(prevout) = utxos.get(&input.prev, input.output_index) else{
+                return Err(MempoolError::MissingInputs)
+            };
+            prevouts.push(prevout);
+        }
+
+        if !UTXOS::validate_scripts_with(tx, &prevouts){
+            return Err(MempoolError::InvalidScript)
+        }
+
+        let input_value = UTXOS::get_output_value(prevouts);
+        let output_value = UTXOS::get_output_value(tx.outputs.clone());
+        if input_value < output_value{
+            return Err(MempoolError::OutputsExceedInputs)
+        }
+        let fee = input_value - output_value;
+
+        let required = self.get_min_fee_rate(get_timestamp());
+        let rate = fee_rate(self.get_modified_fee(&tx.get_hash(), fee), tx.get_size());
+        if rate < required{
+            return Err(MempoolError::FeeRateTooLow { required, offered: rate })
+        }
+
+        if !conflicts.is_empty(){
+            Self::check_replacement(tx, fee, &conflicts, &replaced)?;
+        }
+
+        Ok(Admission { 
+            fee, 
+            replaces: replaced.into_iter().map(|entry| entry.transaction).collect() 
+        })
+    }
+
+    //replacements must be signalled and pay more in total and per byte than what they evict
+    fn check_replacement(
+        tx: &Transaction, 
+        fee: usize, 
+        conflicts: &[TransactionWithFee], 
+        replaced: &[TransactionWithFee]
+    ) -> Result<(), MempoolError>{
+        if !conflicts.iter().all(|entry| entry.transaction.is_replaceable()){
+            return Err(MempoolError::NotReplaceable)
+        }
+
+        let size = tx.get_size();
+        let rate = fee_rate(fee, size);
+        for entry in conflicts{
+            let conflict_rate = entry.get_fee_rate();
+            if rate <= conflict_rate{
+                return Err(MempoolError::ReplacementFeeRateTooLow { 
+                    required: conflict_rate + 1, 
+                    offered: rate 
+                })
+            }
+        }
+
+        let required = replaced.iter().map(|entry| entry.fee).sum::<usize>() 
+            + fee_for_rate(MIN_FEE_RATE, size);
+        if fee < required{
+            return Err(MempoolError::InsufficientReplacementFee { required, offered: fee })
+        }
+        Ok(())
+    }
+
+    //returns mempool transactions evicted because the block confirmed a competing spend
+    pub fn add_block(&mut self, block: &Block) -> Vec<Transaction>{
+        let transactions = block.get_relayed_transactions();
+        self.remove(transactions.clone());
+        for tx in transactions.iter(){
+            self.deltas.remove(&tx.get_hash());
+        }
+
+        let mut conflicts = Vec::new();
+        for tx in transactions.iter(){
+            for entry in self.get_conflicts(tx){
+                if !conflicts.contains(&entry){
+                    conflicts.push(entry);
+                }
+            }
+        }
+        let evicted: Vec<Transaction> = self.with_descendants(conflicts).into_iter()
+            .map(|entry| entry.transaction)
+            .collect();
+        self.remove(evicted.clone());
+        evicted
     }
 
+    //returns what was evicted to stay within the size limit, which may include the new transaction
     pub fn add_transaction(
         &mut self, 
         transaction: Transaction, 
         fee: usize
-    ){
-        self.push(transaction, fee);
+    ) -> Vec<Transaction>{
+        self.add_transaction_at(transaction, fee, get_timestamp())
+    }
+
+    //keeps the original arrival time of a reloaded entry so it still expires on schedule
+    pub fn add_transaction_at(
+        &mut self, 
+        transaction: Transaction, 
+        fee: usize,
+        added: usize,
+    ) -> Vec<Transaction>{
+        self.push(transaction, fee, added);
+        self.trim(get_timestamp())
     }
 
+    //highest modified fee rate first, each transaction pays its fee to the miner in a new first output
+    //fees line up with the transactions after the coinbase
     pub async fn get_next_transactions(
         &mut self, 
         utxos: Arc<RwLock<UTXOS>>, 
         public_key: PublicKey,
         reward: usize,
         version: usize,
-    ) -> Vec<Transaction>{
+    ) -> (Vec<Transaction>, Vec<usize>){
+        let mut working_utxos = utxos.read().await.clone();
+        let mut entries: Vec<TransactionWithFee> = self.to_vec().into_iter()
+            .map(|entry| {
+                let fee = self.get_modified_fee(&entry.transaction.get_hash(), entry.fee);
+                TransactionWithFee::new(entry.transaction, fee)
+            })
+            .collect();
+        entries.sort_by_key(|entry| Reverse(entry.clone()));
+
         let mut txs = vec![Transaction::reward(reward, public_key.clone(), version)];
+        let mut fees = Vec::new();
         let mut invalid_txs = Vec::new();
+        let mut budget = MAX_BLOCK_SIZE.saturating_sub(txs[0].get_size());
 
-        let mut temp_mempool = self.clone();
-
-        while txs.len() < TRANSACTIONS_PER_BLOCK{
-            let Some(TransactionWithFee{transaction: mut tx, fee}) = temp_mempool.pop() else{
-                temp_mempool.remove(invalid_txs);
-                return txs
-            };
-
-            let is_valid = {
-                let utxos_read = utxos.read().await;
-                utxos_read.validate_pending_transaction(&tx)
-            };
-
-            if is_valid{
-                tx.add_fee(public_key.clone(), fee);
-                txs.push(tx);
-            }else{
+        for TransactionWithFee{transaction: tx, ..} in entries{
+            if !working_utxos.validate_pending_transaction(&tx){
                 warn!("Invalid tx");
                 invalid_txs.push(tx);
+                continue
+            }
+
+            //the miner is paid the real fee, whatever delta was used to rank it
+            let mut mined = tx;
+            let fee = working_utxos.calculate_fee(&mined);
+            mined.add_fee(public_key.clone(), fee);
+            if mined.get_size() > budget{
+                continue
             }
+            budget -= mined.get_size();
+            working_utxos.add_transaction(mined.clone());
+            txs.push(mined);
+            fees.push(fee);
         }
         self.remove(invalid_txs);
-        txs
+        (txs, fees)
     }
 
-    pub fn update(
-        &mut self, 
-        mempool: Mempool,
-    ){
-        self.0.update(mempool.0)
+    pub fn size(&self) -> usize{
+        self.entries.len()
     }
 
-    pub fn size(&self) -> usize{
-        self.0.size()
+    //fee rate of the transaction sitting the given number of bytes deep, None if the mempool is smaller
+    pub fn get_fee_rate_at(&self, depth: usize) -> Option<usize>{
+        let mut entries = self.to_vec();
+        entries.sort_by_key(|entry| Reverse(entry.clone()));
+
+        let mut size = 0;
+        for entry in entries{
+            size += entry.get_size();
+            if size >= depth{
+                return Some(entry.get_fee_rate())
+            }
+        }
+        None
     }
 }
 
@@ -202,24 +495,130 @@ impl Serialize for Mempool{
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+struct FeeDelta{
+    delta: isize,
+    set: usize,
+}
+
+#[derive(Debug)]
+pub struct Admission{
+    pub fee: usize,
+    pub replaces: Vec<Transaction>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MempoolError{
+    AlreadyKnown,
+    MissingInputs,
+    InputsSpent,
+    DuplicateInputs,
+    InvalidScript,
+    OutputsExceedInputs,
+    NotReplaceable,
+    SpendsConflict,
+    ReplacementFeeRateTooLow{
+        required: usize,
+        offered: usize,
+    },
+    InsufficientReplacementFee{
+        required: usize,
+        offered: usize,
+    },
+    FeeRateTooLow{
+        required: usize,
+        offered: usize,
+    },
+    MempoolFull,
+    SpendsUnconfirmed,
+}
+
+impl fmt::Display for MempoolError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            MempoolError::AlreadyKnown => write!(f, "Transaction already in mempool"),
+            MempoolError::MissingInputs => write!(f, "Transaction spends outputs that are not known yet"),
+            MempoolError::InputsSpent => write!(f, "Transaction spends outputs that are already spent"),
+            MempoolError::DuplicateInputs => write!(f, "Transaction spends the same output twice"),
+            MempoolError::InvalidScript => write!(f, "Transaction script validation failed"),
+            MempoolError::OutputsExceedInputs => write!(f, "Transaction outputs exceed inputs"),
+            MempoolError::NotReplaceable => write!(f, "Conflicts with a transaction that does not signal replaceability"),
+            MempoolError::SpendsConflict => write!(f, "Transaction spends an output of a transaction it replaces"),
+            MempoolError::ReplacementFeeRateTooLow { required, offered } => {
+                write!(f, "Replacement fee rate too low: required {}, offered {}", required, offered)
+            }
+            MempoolError::InsufficientReplacementFee { required, offered } => {
+                write!(f, "Replacement fee too low: required {}, offered {}", required, offered)
+            }
+            MempoolError::FeeRateTooLow { required, offered } => {
+                write!(f, "Fee rate below the minimum relay fee rate: required {}, offered {}", required, offered)
+            }
+            MempoolError::MempoolFull => write!(f, "Mempool is full and the fee rate is too low to evict anything"),
+            MempoolError::SpendsUnconfirmed => write!(f, "Transaction spends an output of an unconfirmed transaction"),
+        }
+    }
+}
+
+impl std::error::Error for MempoolError{}
+
+//what the mempool dump stores, fees are worked out again when it is reloaded
 #[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MempoolEntry{
+    pub transaction: Transaction,
+    pub added: usize,
+    #[serde(default)]
+    pub fee_delta: isize,
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct TransactionWithFee{
     pub transaction: Transaction,
     pub fee: usize,
+    //hash and serialized size, kept so ordering does not re-serialize on every comparison
+    #[serde(skip)]
+    txid: Vec<u8>,
+    #[serde(skip)]
+    size: usize,
 }
 
 impl TransactionWithFee{
     fn new(transaction: Transaction, fee: usize) -> Self{
+        let txid = transaction.get_hash();
+        let size = transaction.get_size();
         Self { 
             transaction, 
-            fee 
+            fee,
+            txid,
+            size,
+        }
+    }
+
+    pub fn get_size(&self) -> usize{
+        self.size
+    }
+
+    pub fn get_fee_rate(&self) -> usize{
+        fee_rate(self.fee, self.size)
+    }
+}
+
+impl <'de>Deserialize<'de> for TransactionWithFee{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Entry{
+            transaction: Transaction,
+            fee: usize,
         }
+        let Entry{transaction, fee} = Entry::deserialize(deserializer)?;
+        Ok(TransactionWithFee::new(transaction, fee))
     }
 }
 
 impl PartialEq for TransactionWithFee{
     fn eq(&self, other: &Self) -> bool {
-        self.transaction.get_hash() == other.transaction.get_hash()
+        self.txid == other.txid
     }
 }
 
@@ -227,13 +626,16 @@ impl Eq for TransactionWithFee{}
 
 impl Hash for TransactionWithFee{
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.transaction.hash(state)
+        self.txid.hash(state)
     }
 }
 
+//by fee per byte, cross multiplied so small transactions are not rounded down to the same rate
 impl Ord for TransactionWithFee{
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.fee.cmp(&other.fee)
+        (self.fee * other.size).cmp(&(other.fee * self.size))
+            .then(self.fee.cmp(&other.fee))
+            .then_with(|| self.txid.cmp(&other.txid))
     }
 }
 
@@ -242,3 +644,173 @@ impl PartialOrd for TransactionWithFee{
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::block::{keys::PrivateKey, script::Script, transaction::TxInput};
+
+    fn entry(value: usize, parent: Option<&Transaction>, fee: usize) -> (Vec<u8>, TransactionWithFee){
+        let mut transaction = Transaction::reward(value, PrivateKey::new().get_public_key(), 0);
+        if let Some(parent) = parent{
+            transaction.inputs.push(TxInput { 
+                prev: parent.get_hash(), 
+                output_index: 0, 
+                unlocking_script: Script::P2PKHUnlocking(vec![0; 64], vec![0; 33]) 
+            });
+        }
+        (transaction.get_hash(), TransactionWithFee::new(transaction, fee))
+    }
+
+    #[test]
+    fn ordered_by_fee_rate(){
+        let (_small_id, small) = entry(1, None, 100);
+        let (_large_id, large) = entry(2, None, 150);
+        let mut transaction = large.transaction;
+        transaction.outputs = vec![transaction.outputs[0].clone(); 10];
+        let large = TransactionWithFee::new(transaction, 150);
+        assert!(large.fee > small.fee && large.get_fee_rate() < small.get_fee_rate());
+
+        let mut mempool = Mempool::new();
+        mempool.add_transaction(large.transaction.clone(), large.fee);
+        mempool.add_transaction(small.transaction.clone(), small.fee);
+        assert_eq!(mempool.to_vec().into_iter().max(), Some(small.clone()));
+        assert_eq!(mempool.get_fee_rate_at(1), Some(small.get_fee_rate()));
+        assert_eq!(mempool.get_fee_rate_at(small.transaction.get_size() + 1), Some(large.get_fee_rate()));
+        assert_eq!(mempool.get_fee_rate_at(MAX_BLOCK_SIZE * 10), None);
+    }
+
+    #[test]
+    fn full_mempool_evicts_lowest_fee_rate(){
+        let (_parent_id, parent) = entry(1, None, 10);
+        let (_child_id, child) = entry(2, Some(&parent.transaction), 500);
+        let (_high_id, high) = entry(3, None, 400);
+
+        let mut mempool = Mempool::new();
+        mempool.set_limits(MempoolLimits { 
+            max_size: high.transaction.get_size() + child.transaction.get_size(), 
+            max_age: 100 
+        });
+        assert!(mempool.add_transaction(parent.transaction.clone(), parent.fee).is_empty());
+        assert!(mempool.add_transaction(child.transaction.clone(), child.fee).is_empty());
+
+        //the parent goes and takes its child with it
+        let evicted = mempool.add_transaction(high.transaction.clone(), high.fee);
+        assert_eq!(
+            evicted.iter().map(|tx| tx.get_hash()).collect::<Vec<_>>(), 
+            vec![parent.transaction.get_hash(), child.transaction.get_hash()]
+        );
+        assert!(mempool.contains(&high.transaction));
+
+        let now = get_timestamp();
+        assert_eq!(mempool.get_min_fee_rate(now), parent.get_fee_rate() + INCREMENTAL_FEE_RATE);
+        assert_eq!(mempool.get_min_fee_rate(now + MIN_FEE_HALF_LIFE), (parent.get_fee_rate() + INCREMENTAL_FEE_RATE) / 2);
+        assert_eq!(mempool.get_min_fee_rate(now + MIN_FEE_HALF_LIFE * 64), MIN_FEE_RATE);
+
+        assert!(mempool.expire(now).is_empty());
+        assert_eq!(mempool.expire(now + 101).len(), 1);
+        assert_eq!(mempool.size(), 0);
+    }
+
+    #[test]
+    fn block_evicts_conflicting_spends(){
+        let (_funding_id, funding) = entry(1, None, 0);
+        let (_spend_id, spend) = entry(2, Some(&funding.transaction), 100);
+        let (_child_id, child) = entry(3, Some(&spend.transaction), 100);
+        let (_other_id, other) = entry(4, None, 100);
+
+        let mut mempool = Mempool::new();
+        for entry in [&spend, &child, &other]{
+            mempool.add_transaction(entry.transaction.clone(), entry.fee);
+        }
+        assert_eq!(mempool.get_spender(&funding.transaction.get_hash(), 0), Some(spend.clone()));
+        assert_eq!(mempool.with_descendants(vec![spend.clone()]), vec![spend.clone(), child.clone()]);
+
+        //a different transaction spending the same output confirms
+        let (_double_id, mut double) = entry(5, Some(&funding.transaction), 100);
+        double.transaction.add_fee(PrivateKey::new().get_public_key(), 100);
+        let (_coinbase_id, coinbase) = entry(6, None, 0);
+        let block = Block::new(0, 0, 0, vec![coinbase.transaction, double.transaction], Vec::new());
+        let evicted = mempool.add_block(&block);
+
+        assert_eq!(evicted.len(), 2);
+        assert!(!mempool.contains(&spend.transaction) && !mempool.contains(&child.transaction));
+        assert!(mempool.contains(&other.transaction));
+        assert_eq!(mempool.get_spender(&funding.transaction.get_hash(), 0), None);
+    }
+
+    #[test]
+    fn fee_deltas_accumulate(){
+        let mut mempool = Mempool::new();
+        let txid = vec![1; 32];
+
+        mempool.prioritise(txid.clone(), 500, 0);
+        mempool.prioritise(txid.clone(), 250, 0);
+        assert_eq!(mempool.get_modified_fee(&txid, 100), 850);
+
+        mempool.prioritise(txid.clone(), -1000, 0);
+        assert_eq!(mempool.get_modified_fee(&txid, 100), 0);
+
+        mempool.prioritise(txid.clone(), 250, 0);
+        assert_eq!(mempool.get_fee_delta(&txid), 0);
+        assert!(mempool.deltas.is_empty());
+    }
+
+    #[test]
+    fn fee_deltas_for_missing_transactions_are_bounded(){
+        let (present_id, present) = entry(1, None, 100);
+        let now = get_timestamp();
+        let mut mempool = Mempool::new();
+        mempool.set_limits(MempoolLimits { max_size: DEFAULT_MAX_MEMPOOL_SIZE, max_age: 100 });
+        mempool.add_transaction_at(present.transaction.clone(), present.fee, now + MAX_FEE_DELTAS + 100);
+
+        mempool.prioritise(present_id.clone(), 500, now);
+        for index in 0..MAX_FEE_DELTAS{
+            mempool.prioritise((index as u32).to_be_bytes().to_vec(), 500, now + 1 + index);
+        }
+        assert_eq!(mempool.deltas.len(), MAX_FEE_DELTAS);
+        assert_eq!(mempool.get_fee_delta(&present_id), 500);
+        assert_eq!(mempool.get_fee_delta(&0u32.to_be_bytes().to_vec()), 0);
+
+        mempool.expire(now + 1 + MAX_FEE_DELTAS + 100);
+        assert!(mempool.contains(&present.transaction));
+        assert_eq!(mempool.deltas.len(), 1);
+    }
+
+    #[test]
+    fn reloaded_entries_keep_their_age(){
+        let (_parent_id, parent) = entry(1, None, 0);
+        let (_child_id, child) = entry(2, Some(&parent.transaction), 100);
+        let now = get_timestamp();
+
+        let mut mempool = Mempool::new();
+        mempool.add_transaction_at(child.transaction.clone(), child.fee, now);
+        mempool.add_transaction_at(parent.transaction.clone(), parent.fee, now - DEFAULT_MEMPOOL_EXPIRY);
+
+        let entries = mempool.get_entries();
+        assert_eq!(entries[0].transaction.get_hash(), parent.transaction.get_hash());
+        assert_eq!(entries[0].added, now - DEFAULT_MEMPOOL_EXPIRY);
+
+        assert_eq!(mempool.enforce_limits(now).len(), 2);
+        assert_eq!(mempool.size(), 0);
+    }
+
+    #[test]
+    fn unconfirmed_outputs_cannot_be_spent(){
+        let (_funding_id, funding) = entry(1, None, 0);
+        let mut utxos = UTXOS::new();
+        utxos.add_transaction(funding.transaction.clone());
+
+        let mut mempool = Mempool::new();
+        let (_parent_id, parent) = entry(2, Some(&funding.transaction), 100);
+        mempool.add_transaction(parent.transaction.clone(), parent.fee);
+
+        assert!(mempool.get_spender(&funding.transaction.get_hash(), 0).is_some());
+
+        let (_child_id, child) = entry(3, Some(&parent.transaction), 100);
+        assert_eq!(
+            mempool.check_transaction(&child.transaction, &utxos).unwrap_err(), 
+            MempoolError::SpendsUnconfirmed
+        );
+    }
+}
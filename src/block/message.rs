@@ -0,0 +1,59 @@
+const MESSAGE_PREFIX: &[u8] = b"COMP-COIN Signed Message:\n";
+
+const PUBLIC_KEY_LENGTH: usize = 33;
+
+use anyhow::{Result, anyhow};
+
+use super::{
+    address::Address,
+    keys::{PrivateKey, PublicKey},
+};
+
+use crate::utils::sha256;
+
+//the prefix keeps a message signature from ever being valid for a transaction sighash
+fn message_hash(message: &str) -> Vec<u8>{
+    let mut bytes = MESSAGE_PREFIX.to_vec();
+    bytes.extend((message.len() as u64).to_le_bytes());
+    bytes.extend(message.as_bytes());
+    sha256(bytes)
+}
+
+//the public key is included so the verifier only needs the address
+pub fn sign_message(private_key: &PrivateKey, message: &str) -> String{
+    let mut bytes = private_key.get_public_key().to_vec();
+    bytes.extend(private_key.sign(message_hash(message)));
+    bs58::encode(bytes).into_string()
+}
+
+pub fn verify_message(address: &Address, message: &str, signature: &str) -> Result<bool>{
+    let bytes = bs58::decode(signature.trim()).into_vec()?;
+    if bytes.len() <= PUBLIC_KEY_LENGTH{
+        return Err(anyhow!("Signature is too short"))
+    }
+    let (public_key, signature) = bytes.split_at(PUBLIC_KEY_LENGTH);
+    let public_key = PublicKey::from_bytes(public_key.to_vec())?;
+
+    Ok(public_key.to_address() == *address && public_key.verify_sig(message_hash(message), signature.to_vec()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn sign_and_verify(){
+        let private_key = PrivateKey::new();
+        let address = private_key.get_public_key().to_address();
+        let signature = sign_message(&private_key, "I own this address");
+
+        assert!(verify_message(&address, "I own this address", &signature).unwrap());
+        assert!(!verify_message(&address, "I own that address", &signature).unwrap());
+        assert!(!verify_message(&PrivateKey::new().get_public_key().to_address(), "I own this address", &signature).unwrap());
+
+        //a plain signature over the same bytes, as a transaction input would carry, is rejected
+        let mut raw = private_key.get_public_key().to_vec();
+        raw.extend(private_key.sign(b"I own this address".to_vec()));
+        assert!(!verify_message(&address, "I own this address", &bs58::encode(raw).into_string()).unwrap());
+    }
+}
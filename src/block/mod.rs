@@ -5,11 +5,34 @@ mod transaction;
 mod utxos;
 mod wallet;
 mod keys;
+mod hd;
+mod keystore;
+mod coin_selection;
+mod history;
+mod address;
+mod fees;
+mod psbt;
+mod message;
+mod invoice;
+mod orphans;
+mod template;
+mod job;
 
 pub use {
-    wallet::Wallet,
-    mempool::Mempool,
+    wallet::{DEFAULT_WALLET, Fee, OpenSecrets, Wallet, WalletError, TransactionPlan, UtxoView, parse_outpoint},
+    address::{Address, AddressError},
+    keys::PublicKey,
+    psbt::{Psbt, PsbtError},
+    message::verify_message,
+    invoice::{InvoiceView, PaymentUri},
+    fees::{FeeEstimate, FeeEstimator, DEFAULT_TARGET, MAX_TARGET, fee_rate},
+    coin_selection::CoinSelection,
+    history::HistoryView,
+    mempool::{Admission, DEFAULT_MAX_MEMPOOL_SIZE, DEFAULT_MEMPOOL_EXPIRY, Mempool, MempoolError, MempoolEntry, MempoolLimits, TransactionWithFee},
+    orphans::OrphanPool,
     utxos::UTXOS,
-    block::Block,
+    block::{Block, BlockError},
+    template::BlockTemplate,
+    job::{MiningJob, Solution},
     transaction::{Transaction, TransactionSpec, OutputSpec}
 };
\ No newline at end of file
@@ -0,0 +1,165 @@
+const MAX_ORPHANS: usize = 100;
+
+//orphans whose parents never show up are dropped after 20 minutes
+const ORPHAN_EXPIRY: usize = 20 * 60;
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+};
+
+use super::{Block, Transaction};
+
+#[derive(Clone, Debug)]
+pub struct Orphan{
+    pub transaction: Transaction,
+    pub peer: SocketAddr,
+    missing: Vec<Vec<u8>>,
+    added: usize,
+}
+
+//transactions received before their parents, indexed by the parents they are waiting for
+#[derive(Clone, Debug, Default)]
+pub struct OrphanPool{
+    orphans: HashMap<Vec<u8>, Orphan>,
+    by_parent: HashMap<Vec<u8>, HashSet<Vec<u8>>>,
+}
+
+impl OrphanPool{
+    pub fn len(&self) -> usize{
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.orphans.is_empty()
+    }
+
+    pub fn contains(&self, txid: &Vec<u8>) -> bool{
+        self.orphans.contains_key(txid)
+    }
+
+    //false if the orphan was already held, the oldest orphan makes room when the pool is full
+    pub fn add(&mut self, transaction: Transaction, missing: Vec<Vec<u8>>, peer: SocketAddr, now: usize) -> bool{
+        let txid = transaction.get_hash();
+        if self.contains(&txid) || missing.is_empty(){
+            return false
+        }
+
+        self.expire(now);
+        while self.len() >= MAX_ORPHANS{
+            let Some(oldest) = self.orphans.iter()
+                .min_by_key(|(_txid, orphan)| orphan.added)
+                .map(|(txid, _orphan)| txid.clone()) else{
+                break
+            };
+            self.remove(&oldest);
+        }
+
+        for parent in missing.iter(){
+            self.by_parent.entry(parent.clone()).or_default().insert(txid.clone());
+        }
+        self.orphans.insert(txid, Orphan { transaction, peer, missing, added: now });
+        true
+    }
+
+    pub fn remove(&mut self, txid: &Vec<u8>) -> Option<Orphan>{
+        let orphan = self.orphans.remove(txid)?;
+        for parent in orphan.missing.iter(){
+            if let Some(children) = self.by_parent.get_mut(parent){
+                children.remove(txid);
+                if children.is_empty(){
+                    self.by_parent.remove(parent);
+                }
+            }
+        }
+        Some(orphan)
+    }
+
+    //orphans waiting on the parent, removed so the caller can try them again
+    pub fn take_children(&mut self, parent: &Vec<u8>) -> Vec<Orphan>{
+        let children = self.by_parent.remove(parent).unwrap_or_default();
+        children.iter().filter_map(|txid| self.remove(txid)).collect()
+    }
+
+    pub fn remove_block(&mut self, block: &Block){
+        for transaction in block.get_relayed_transactions(){
+            self.remove(&transaction.get_hash());
+        }
+    }
+
+    pub fn expire(&mut self, now: usize) -> usize{
+        let expired: Vec<Vec<u8>> = self.orphans.iter()
+            .filter(|(_txid, orphan)| orphan.added + ORPHAN_EXPIRY <= now)
+            .map(|(txid, _orphan)| txid.clone())
+            .collect();
+        for txid in expired.iter(){
+            self.remove(txid);
+        }
+        expired.len()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::block::keys::PrivateKey;
+
+    fn orphan(value: usize) -> Transaction{
+        Transaction::reward(value, PrivateKey::new().get_public_key(), 0)
+    }
+
+    #[test]
+    fn orphans_are_bounded_and_released_by_parent(){
+        let peer: SocketAddr = "127.0.0.1:8333".parse().unwrap();
+        let parent = vec![1; 32];
+        let mut pool = OrphanPool::default();
+
+        let first = orphan(0);
+        assert!(pool.add(first.clone(), vec![parent.clone()], peer, 0));
+        assert!(!pool.add(first.clone(), vec![parent.clone()], peer, 0));
+        assert!(!pool.add(orphan(1), Vec::new(), peer, 0));
+
+        for value in 1..MAX_ORPHANS + 1{
+            pool.add(orphan(value), vec![vec![2; 32]], peer, value);
+        }
+        assert_eq!(pool.len(), MAX_ORPHANS);
+        assert!(!pool.contains(&first.get_hash()));
+        assert!(pool.take_children(&parent).is_empty());
+
+        assert_eq!(pool.take_children(&vec![2; 32]).len(), MAX_ORPHANS);
+        assert!(pool.is_empty());
+
+        pool.add(first.clone(), vec![parent.clone()], peer, 0);
+        assert_eq!(pool.expire(ORPHAN_EXPIRY), 1);
+        assert!(pool.is_empty());
+    }
+
+    //what Node::add_block does: orphans in the block are dropped, orphans of its mined txids are released
+    #[test]
+    fn block_releases_orphans_of_its_transactions(){
+        let peer: SocketAddr = "127.0.0.1:8333".parse().unwrap();
+        let coinbase = orphan(10);
+        let included = orphan(3);
+        let mut mined = included.clone();
+        mined.add_fee(PrivateKey::new().get_public_key(), 0);
+        let block = Block::new(0, 1, 0, vec![coinbase.clone(), mined.clone()], vec![0; 32]);
+
+        let child = orphan(1);
+        let waiting = orphan(2);
+        let mut pool = OrphanPool::default();
+        pool.add(child.clone(), vec![coinbase.get_hash()], peer, 0);
+        pool.add(waiting.clone(), vec![included.get_hash()], peer, 0);
+        pool.add(included.clone(), vec![vec![3; 32]], peer, 0);
+
+        pool.remove_block(&block);
+        assert!(!pool.contains(&included.get_hash()));
+        let released: Vec<Vec<u8>> = block.get_transactions().iter()
+            .flat_map(|transaction| pool.take_children(&transaction.get_hash()))
+            .map(|orphan| orphan.transaction.get_hash())
+            .collect();
+        assert_eq!(released, vec![child.get_hash()]);
+
+        //a child of the relayed txid waits on outputs no block creates, it is left to expire
+        assert!(pool.contains(&waiting.get_hash()));
+    }
+}
@@ -0,0 +1,265 @@
+const MAGIC: &[u8] = b"cpsbt";
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    keys::PrivateKey,
+    script::{Script, compute_sig_hash},
+    transaction::{Transaction, TxOutput},
+};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct PsbtInput{
+    utxo: Option<TxOutput>,
+    public_key: Option<Vec<u8>>,
+    signature: Option<Vec<u8>>,
+}
+
+//an unsigned transaction plus everything a signer needs, passed between machines until every input is signed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Psbt{
+    transaction: Transaction,
+    inputs: Vec<PsbtInput>,
+    change: Vec<usize>,
+    label: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PsbtError{
+    InvalidEncoding(String),
+    AlreadySigned,
+    Mismatch,
+    MissingUtxo(usize),
+    MissingSignature(usize),
+    InvalidSignature(usize),
+}
+
+impl fmt::Display for PsbtError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            PsbtError::InvalidEncoding(e) => write!(f, "Partially signed transaction is not valid: {}", e),
+            PsbtError::AlreadySigned => write!(f, "Transaction already has unlocking scripts"),
+            PsbtError::Mismatch => write!(f, "Partially signed transactions spend different transactions"),
+            PsbtError::MissingUtxo(index) => write!(f, "Missing utxo for input: {}", index),
+            PsbtError::MissingSignature(index) => write!(f, "Missing signature for input: {}", index),
+            PsbtError::InvalidSignature(index) => write!(f, "Invalid signature for input: {}", index),
+        }
+    }
+}
+
+impl std::error::Error for PsbtError{}
+
+impl Psbt{
+    pub fn create(transaction: Transaction) -> Result<Self, PsbtError>{
+        if transaction.inputs.iter().any(|input| !input.unlocking_script.is_empty()){
+            return Err(PsbtError::AlreadySigned)
+        }
+        Ok(Self {
+            inputs: vec![PsbtInput::default(); transaction.inputs.len()],
+            transaction,
+            change: Vec::new(),
+            label: None
+        })
+    }
+
+    pub fn get_transaction(&self) -> &Transaction{
+        &self.transaction
+    }
+
+    pub fn get_label(&self) -> Option<&String>{
+        self.label.as_ref()
+    }
+
+    pub fn set_label(&mut self, label: Option<String>){
+        self.label = label;
+    }
+
+    pub fn get_change(&self) -> &[usize]{
+        &self.change
+    }
+
+    pub fn set_change(&mut self, change: Vec<usize>){
+        self.change = change;
+    }
+
+    pub fn get_outpoints(&self) -> Vec<(Vec<u8>, usize)>{
+        self.transaction.inputs.iter()
+            .map(|input| (input.prev.clone(), input.output_index))
+            .collect()
+    }
+
+    //fills in the spent outputs a signer needs, returns how many were added
+    pub fn update<F>(&mut self, lookup: F) -> usize
+        where
+            F: Fn(&Vec<u8>, usize) -> Option<TxOutput>{
+        let mut added = 0;
+        for (input, psbt_input) in self.transaction.inputs.iter().zip(self.inputs.iter_mut()){
+            if psbt_input.utxo.is_none(){
+                psbt_input.utxo = lookup(&input.prev, input.output_index);
+                added += psbt_input.utxo.is_some() as usize;
+            }
+        }
+        added
+    }
+
+    //public key hashes of the inputs still waiting for a signature
+    pub fn get_unsigned_hashes(&self) -> Vec<Vec<u8>>{
+        let mut hashes = Vec::new();
+        for input in self.inputs.iter().filter(|input| input.signature.is_none()){
+            if let Some(hash) = input.utxo.as_ref().and_then(|utxo| utxo.locking_script.P2PKHLocking_get_public_key_hash())
+                && !hashes.contains(&hash){
+                hashes.push(hash);
+            }
+        }
+        hashes
+    }
+
+    //signs every input locked to the key, returns how many were signed
+    pub fn sign(&mut self, private_key: &PrivateKey) -> usize{
+        let public_key = private_key.get_public_key();
+        let public_key_hash = public_key.to_hash();
+
+        let mut signed = 0;
+        for index in 0..self.inputs.len(){
+            let input = &self.inputs[index];
+            let Some(utxo) = input.utxo.as_ref() else{
+                continue
+            };
+            if input.signature.is_some()
+                || utxo.locking_script.P2PKHLocking_get_public_key_hash().as_ref() != Some(&public_key_hash){
+                continue
+            }
+
+            let signature = private_key.sign(compute_sig_hash(&self.transaction, index, utxo));
+            self.inputs[index].public_key = Some(public_key.to_vec());
+            self.inputs[index].signature = Some(signature);
+            signed += 1;
+        }
+        signed
+    }
+
+    pub fn combine(&mut self, other: Psbt) -> Result<(), PsbtError>{
+        if self.transaction.get_hash() != other.transaction.get_hash(){
+            return Err(PsbtError::Mismatch)
+        }
+
+        for (input, other) in self.inputs.iter_mut().zip(other.inputs){
+            if input.utxo.is_none(){
+                input.utxo = other.utxo;
+            }
+            if input.signature.is_none() && other.signature.is_some(){
+                input.public_key = other.public_key;
+                input.signature = other.signature;
+            }
+        }
+        for index in other.change{
+            if !self.change.contains(&index){
+                self.change.push(index);
+            }
+        }
+        if self.label.is_none(){
+            self.label = other.label;
+        }
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool{
+        self.inputs.iter().all(|input| input.utxo.is_some() && input.signature.is_some())
+    }
+
+    pub fn get_fee(&self) -> Option<usize>{
+        let spent: usize = self.inputs.iter()
+            .map(|input| input.utxo.as_ref().map(|utxo| utxo.value))
+            .sum::<Option<usize>>()?;
+        spent.checked_sub(self.transaction.outputs.iter().map(|output| output.value).sum())
+    }
+
+    //checks every signature, then builds the unlocking scripts
+    pub fn finalize(&self) -> Result<Transaction, PsbtError>{
+        let mut transaction = self.transaction.clone();
+        let mut prevouts = Vec::new();
+
+        for (index, input) in self.inputs.iter().enumerate(){
+            let utxo = input.utxo.clone().ok_or(PsbtError::MissingUtxo(index))?;
+            let (Some(signature), Some(public_key)) = (input.signature.clone(), input.public_key.clone()) else{
+                return Err(PsbtError::MissingSignature(index))
+            };
+            transaction.inputs[index].unlocking_script = Script::P2PKHUnlocking(signature, public_key);
+            prevouts.push(utxo);
+        }
+
+        for (index, (input, utxo)) in transaction.inputs.iter().zip(prevouts.iter()).enumerate(){
+            if !Script::concat(&input.unlocking_script, &utxo.locking_script).validate(&transaction, index, utxo){
+                return Err(PsbtError::InvalidSignature(index))
+            }
+        }
+        Ok(transaction)
+    }
+}
+
+impl fmt::Display for Psbt{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(postcard::to_allocvec(self).map_err(|_| fmt::Error)?);
+        write!(f, "{}", hex::encode(bytes))
+    }
+}
+
+impl FromStr for Psbt{
+    type Err = PsbtError;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(data.trim()).map_err(|e| PsbtError::InvalidEncoding(e.to_string()))?;
+        let Some(payload) = bytes.strip_prefix(MAGIC) else{
+            return Err(PsbtError::InvalidEncoding("missing magic bytes".to_string()))
+        };
+        let psbt: Psbt = postcard::from_bytes(payload).map_err(|e| PsbtError::InvalidEncoding(e.to_string()))?;
+        if psbt.inputs.len() != psbt.transaction.inputs.len(){
+            return Err(PsbtError::InvalidEncoding("input count does not match transaction".to_string()))
+        }
+        Ok(psbt)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::block::{address::Address, transaction::OutputSpec, utxos::UTXOS};
+
+    fn utxo(private_key: &PrivateKey, value: usize) -> TxOutput{
+        TxOutput { value, locking_script: Script::P2PKHLocking(private_key.get_public_key().to_hash()) }
+    }
+
+    #[test]
+    fn two_signers_combine_and_finalize(){
+        let alice = PrivateKey::new();
+        let bob = PrivateKey::new();
+        let outpoints = vec![(vec![1; 32], 0), (vec![2; 32], 1)];
+        let prevouts = vec![utxo(&alice, 50), utxo(&bob, 30)];
+
+        let transaction = Transaction::unsigned(
+            0,
+            outpoints.clone(),
+            vec![OutputSpec::new(70, Address::from(&alice.get_public_key()))],
+            false
+        );
+        let mut psbt = Psbt::create(transaction).unwrap();
+        assert_eq!(psbt.update(|hash, index| outpoints.iter()
+            .position(|outpoint| outpoint == &(hash.clone(), index))
+            .map(|position| prevouts[position].clone())
+        ), 2);
+        assert_eq!(psbt.get_fee(), Some(10));
+
+        let mut bobs: Psbt = psbt.to_string().parse().unwrap();
+        assert_eq!(psbt.sign(&alice), 1);
+        assert_eq!(bobs.sign(&bob), 1);
+        assert_eq!(psbt.finalize().unwrap_err(), PsbtError::MissingSignature(1));
+
+        psbt.combine(bobs).unwrap();
+        assert!(psbt.is_complete());
+        let transaction = psbt.finalize().unwrap();
+        assert!(UTXOS::validate_scripts_with(&transaction, &prevouts));
+    }
+}
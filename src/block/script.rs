@@ -41,6 +41,10 @@ impl Script{
         Self(vec![])
     }
 
+    pub fn is_empty(&self) -> bool{
+        self.0.is_empty()
+    }
+
     pub fn validate(
         &self,
         tx: &Transaction,
@@ -132,6 +136,14 @@ impl Script{
         ])
     }
 
+    pub fn P2PKHUnlocking_get_public_key(&self) -> Option<Vec<u8>>{
+        let Some(OpCode::PUSHBYTES(public_key)) = self.0.get(1) else{
+            return None
+        };
+
+        Some(public_key.clone())
+    }
+
     pub fn P2PKHLocking_get_public_key_hash(&self) -> Option<Vec<u8>>{
         let Some(OpCode::PUSHBYTES(hash)) = self.0.get(2) else{
             return None
@@ -0,0 +1,61 @@
+use serde::Serialize;
+
+use super::{Block, Transaction};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TemplateTransaction{
+    pub txid: String,
+    pub data: String,
+    pub fee: usize,
+    pub size: usize,
+}
+
+//everything an external miner needs to work on the next block, byte fields are hex
+#[derive(Clone, Debug, Serialize)]
+pub struct BlockTemplate{
+    pub height: usize,
+    pub version: usize,
+    pub prev_hash: String,
+    //leading zero bytes the block hash needs
+    pub difficulty: usize,
+    pub timestamp: usize,
+    pub reward: usize,
+    //paid to the miner in the first output of each transaction, the coinbase only carries the reward
+    pub fees: usize,
+    pub coinbase: String,
+    //excludes the coinbase
+    pub transactions: Vec<TemplateTransaction>,
+    pub merkle_root: String,
+    //empty when the coinbase is the only transaction, the root is then sha256(coinbase || coinbase)
+    pub merkle_branch: Vec<String>,
+    //the whole candidate block, only the nonce needs changing
+    pub block: String,
+}
+
+impl BlockTemplate{
+    //fees line up with the non-coinbase transactions
+    pub fn new(block: &Block, reward: usize, fees: Vec<usize>) -> Self{
+        let transactions = block.get_transactions();
+        let coinbase = transactions.first().map(Transaction::to_hex).unwrap_or_default();
+
+        Self {
+            height: block.get_height(),
+            version: block.get_version(),
+            prev_hash: hex::encode(block.get_prev_hash()),
+            difficulty: block.get_difficulty(),
+            timestamp: block.get_timestamp(),
+            reward,
+            fees: fees.iter().sum(),
+            coinbase,
+            transactions: transactions.iter().skip(1).zip(fees).map(|(transaction, fee)| TemplateTransaction {
+                txid: hex::encode(transaction.get_hash()),
+                data: transaction.to_hex(),
+                fee,
+                size: transaction.get_size(),
+            }).collect(),
+            merkle_root: hex::encode(block.get_merkle_root()),
+            merkle_branch: block.get_merkle_branch().iter().map(hex::encode).collect(),
+            block: block.to_hex(),
+        }
+    }
+}
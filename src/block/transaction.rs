@@ -1,3 +1,6 @@
+//a version bit rather than a new field, so the encoding and txids stay the same for older nodes
+const REPLACEABLE_FLAG: usize = 1 << 31;
+
 use log::info;
 use serde::{Serialize, Deserialize}; 
 
@@ -7,6 +10,7 @@ use super::{
     script::Script,
     keys::{PublicKey,PrivateKey},
     script::compute_sig_hash,
+    address::Address,
 };
 
 #[derive(Serialize, Deserialize, Clone, Debug, Hash)]
@@ -22,10 +26,42 @@ impl Transaction{
         postcard::to_allocvec(self).expect("Failed to serialize transaction")
     }
 
+    //raw transactions are passed around as hex of the serialized bytes
+    pub fn to_hex(&self) -> String{
+        hex::encode(self.to_bytes())
+    }
+
+    pub fn from_hex(data: &str) -> anyhow::Result<Self>{
+        let bytes = hex::decode(data.trim())?;
+        Ok(postcard::from_bytes(&bytes)?)
+    }
+
     pub fn get_hash(&self) -> Vec<u8>{
         sha256(self.to_bytes())
     }
 
+    pub fn get_size(&self) -> usize{
+        self.to_bytes().len()
+    }
+
+    //size of a signed p2pkh transaction, used to price fees before inputs are signed
+    pub fn estimate_size(input_count: usize, output_count: usize) -> usize{
+        let transaction = Self {
+            timestamp: get_timestamp(),
+            version: 0,
+            inputs: vec![TxInput {
+                prev: vec![0; 32],
+                output_index: 0,
+                unlocking_script: Script::P2PKHUnlocking(vec![0; 64], vec![0; 33])
+            }; input_count],
+            outputs: vec![TxOutput {
+                value: usize::MAX,
+                locking_script: Script::P2PKHLocking(vec![0; 32])
+            }; output_count],
+        };
+        transaction.get_size()
+    }
+
     pub fn reward(reward: usize, public_key: PublicKey, version: usize) -> Self{
         Self{
             timestamp: get_timestamp(),
@@ -38,6 +74,7 @@ impl Transaction{
         }
     }
 
+    //miners collect the fee as a new first output, which changes the txid once mined
     pub fn add_fee(&mut self, public_key: PublicKey, fee: usize){
         self.outputs.insert(0, TxOutput { 
             value: fee, 
@@ -49,6 +86,46 @@ impl Transaction{
         self.outputs.remove(0);
     }
 
+    //the transaction as it was relayed, before a miner added the fee output
+    pub fn without_fee(&self) -> Self{
+        let mut transaction = self.clone();
+        if !transaction.outputs.is_empty(){
+            transaction.remove_fee();
+        }
+        transaction
+    }
+
+    //inputs have empty unlocking scripts until a partially signed transaction collects the signatures
+    pub fn unsigned(
+        version: usize, 
+        outpoints: Vec<(Vec<u8>, usize)>, 
+        outputs: Vec<OutputSpec>, 
+        replaceable: bool
+    ) -> Self{
+        Self { 
+            timestamp: get_timestamp(), 
+            version, 
+            inputs: outpoints.into_iter().map(|(prev, output_index)| TxInput {
+                prev,
+                output_index,
+                unlocking_script: Script::empty()
+            }).collect(), 
+            outputs: outputs.iter().map(|output| output.to_tx_output()).collect(), 
+        }.with_replaceable(replaceable)
+    }
+
+    fn with_replaceable(mut self, replaceable: bool) -> Self{
+        if replaceable{
+            self.version |= REPLACEABLE_FLAG;
+        }
+        self
+    }
+
+    //signals that the sender accepts replacement by a higher fee spend of the same inputs
+    pub fn is_replaceable(&self) -> bool{
+        self.version & REPLACEABLE_FLAG != 0
+    }
+
     pub fn debug(&self){
         info!("Transaction");
         info!("Inputs:");
@@ -78,7 +155,7 @@ pub struct TxOutput{
 
 pub struct OutputSpec{
     pub value: usize,
-    pub recipient: Vec<u8>,
+    pub recipient: Address,
 }
 
 impl OutputSpec{
@@ -86,12 +163,12 @@ impl OutputSpec{
         TxOutput { 
             value: self.value, 
             locking_script: Script::P2PKHLocking(
-                sha256(self.recipient.clone())
+                self.recipient.get_public_key_hash()
             ) 
         }
     }
 
-    pub fn new(value: usize, recipient: Vec<u8>) -> Self{
+    pub fn new(value: usize, recipient: Address) -> Self{
         Self { 
             value, 
             recipient 
@@ -103,6 +180,7 @@ pub struct InputSpec{
     prev: Vec<u8>,
     output_index: usize,
     pub utxo: TxOutput,
+    private_key: PrivateKey,
 }
 
 impl InputSpec{
@@ -139,22 +217,23 @@ impl InputSpec{
     pub fn new(
         prev: Vec<u8>,
         output_index: usize,
-        utxo: TxOutput
+        utxo: TxOutput,
+        private_key: PrivateKey,
     ) -> Self{
         Self { 
             prev, 
             output_index, 
-            utxo
+            utxo,
+            private_key,
         }
     }
 }
 
 pub struct TransactionSpec{
-    pub public_key: PublicKey,
-    pub private_key: PrivateKey,
     pub inputs: Vec<InputSpec>,
     pub outputs: Vec<OutputSpec>,
     pub version: usize,
+    pub replaceable: bool,
 }
 
 
@@ -165,14 +244,15 @@ impl TransactionSpec{
         let mut transaction = self.to_sig_transaction();
         
         for index in 0..transaction.inputs.len(){
+            let input = self.inputs.get(index).unwrap();
             transaction.inputs[index].unlocking_script = Script::P2PKHUnlocking(
-                self.private_key.sign(
+                input.private_key.sign(
                     compute_sig_hash(
                         &transaction.clone(), 
                         index, 
-                        &self.inputs.get(index).unwrap().utxo
+                        &input.utxo
                     )),
-                 self.public_key.to_vec()
+                 input.private_key.get_public_key().to_vec()
                 ) 
         }
         
@@ -193,22 +273,20 @@ impl TransactionSpec{
             outputs: self.outputs.iter().map(
                 |output| 
                 output.to_tx_output()
-            ).collect()
-        }
+            ).collect(),
+        }.with_replaceable(self.replaceable)
     }
 
     pub fn pre_inputs(
         version: usize, 
         outputs: Vec<OutputSpec>, 
-        public_key: PublicKey, 
-        private_key: PrivateKey
+        replaceable: bool,
     ) -> Self{
         Self {
-            public_key, 
-            private_key, 
             inputs: vec![], 
             outputs, 
-            version 
+            version,
+            replaceable,
         }
     }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use std::{collections::HashMap};
+use std::{collections::{HashMap, HashSet}};
 
 #[allow(unused_imports)]
 use log::{info, warn};
@@ -9,7 +9,6 @@ use super::{
     transaction::{TxOutput, Transaction, TxInput},
     block::Block,
     script::Script,
-    mempool::{Mempool,TransactionWithFee}
 };
 
 #[derive(Clone, Debug)]
@@ -28,11 +27,11 @@ impl UTXOS{
         self.0.remove(&(hash, index));
     }
 
-    fn get(&self, hash: &Vec<u8>, index: usize) -> Option<TxOutput>{
+    pub fn get(&self, hash: &Vec<u8>, index: usize) -> Option<TxOutput>{
         self.0.get(&(hash.clone(), index)).cloned()
     }
 
-    fn add_transaction(&mut self, tx: Transaction){
+    pub(super) fn add_transaction(&mut self, tx: Transaction){
          
         let hash = tx.get_hash();
 
@@ -51,13 +50,22 @@ impl UTXOS{
         }
     }
     fn validate_scripts(&self, tx: &Transaction) -> bool{
-        for (index, input) in tx.inputs.iter().enumerate(){
+        let mut prevouts = Vec::new();
+        for input in tx.inputs.iter(){
             let Some(utxo) = self.get(&input.prev, input.output_index) else{
                 warn!("Could not get utxo: {}, {}", hex::encode(&input.prev), input.output_index);
                 return false
             };
-            
-            if !Script::concat(&input.unlocking_script, &utxo.locking_script).validate(&tx, index, &utxo){
+            prevouts.push(utxo);
+        }
+
+        Self::validate_scripts_with(tx, &prevouts)
+    }
+
+    //prevouts are the outputs spent by each input, in input order
+    pub fn validate_scripts_with(tx: &Transaction, prevouts: &[TxOutput]) -> bool{
+        for (index, (input, utxo)) in tx.inputs.iter().zip(prevouts).enumerate(){
+            if !Script::concat(&input.unlocking_script, &utxo.locking_script).validate(tx, index, utxo){
                 warn!("script validation failed for script: {:?}", Script::concat(&input.unlocking_script, &utxo.locking_script));
                 return false 
             }
@@ -66,16 +74,19 @@ impl UTXOS{
         true
     }
 
-    fn validate_confirmed_transaction(&self, tx: &Transaction) -> bool{
-        let mut tx_clone = tx.clone();
-        tx_clone.remove_fee();
-        if !self.validate_scripts(&tx_clone){
-            return false}
-
-        self.get_input_value(tx.inputs.clone()) == UTXOS::get_output_value(tx.outputs.clone())
+    pub fn has_duplicate_inputs(tx: &Transaction) -> bool{
+        let outpoints: HashSet<(&Vec<u8>, usize)> = tx.inputs.iter()
+            .map(|input| (&input.prev, input.output_index))
+            .collect();
+        outpoints.len() != tx.inputs.len()
     }
 
     pub fn validate_pending_transaction(&self, tx: &Transaction) -> bool{
+        if tx.inputs.is_empty() || Self::has_duplicate_inputs(tx){
+            warn!("Invalid inputs");
+            return false
+        }
+
         if !self.validate_scripts(&tx){
             warn!("Invalid script");
             return false
@@ -94,10 +105,22 @@ impl UTXOS{
         input_amount
     }
 
-    fn get_output_value(outputs: Vec<TxOutput>) -> usize{
+    pub fn get_output_value(outputs: Vec<TxOutput>) -> usize{
         outputs.iter().map(|utxo| utxo.value).sum()
     }
 
+    //signatures cover the transaction as relayed, the miner's fee output must make inputs and outputs balance
+    fn validate_confirmed_transaction(&self, tx: &Transaction) -> bool{
+        if tx.outputs.is_empty(){
+            return false
+        }
+        if !self.validate_pending_transaction(&tx.without_fee()){
+            return false
+        }
+
+        self.get_input_value(tx.inputs.clone()) == UTXOS::get_output_value(tx.outputs.clone())
+    }
+
     pub fn is_coinbase(transaction: &Transaction, reward: usize) -> bool{
         transaction.inputs.len() == 0 
         && transaction.outputs.len() == 1
@@ -115,11 +138,17 @@ impl UTXOS{
             return false
         }
 
-        for tx in &block.get_transactions()[1..]{
+        //every transaction spends confirmed outputs, none of them twice
+        let mut spent = HashSet::new();
+        for tx in &txs[1..]{
             if !self.validate_confirmed_transaction(tx){
                 warn!("Invalid transaction");
                 return false
             }
+            if !tx.inputs.iter().all(|input| spent.insert((&input.prev, input.output_index))){
+                warn!("Block spends an output twice");
+                return false
+            }
         }
 
         true
@@ -128,14 +157,6 @@ impl UTXOS{
     pub fn calculate_fee(&self, transaction: &Transaction) -> usize{
         self.get_input_value(transaction.inputs.clone()) - Self::get_output_value(transaction.outputs.clone())
     }
-
-    pub fn validate_mempool(&self, mempool: &Mempool) -> bool{
-        for TransactionWithFee{transaction, fee} in mempool.to_vec(){
-            if !self.validate_pending_transaction(&transaction){return false}
-            if self.calculate_fee(&transaction) != fee {return false}
-        }
-        return true
-    }
 }
 
 impl Serialize for UTXOS{
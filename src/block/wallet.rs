@@ -1,8 +1,28 @@
-use std::collections::HashMap;
+pub const DEFAULT_WALLET: &str = "default";
+
+const RECEIVE_CHAIN: u32 = 0;
+const CHANGE_CHAIN: u32 = 1;
+
+const MAX_FEE_ROUNDS: usize = 10;
+
+const MIN_CONSOLIDATION_INPUTS: usize = 2;
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+
+use bip39::Mnemonic;
 
 #[allow(unused_imports)]
 use log::{info, warn};
 
+use rand::RngCore;
+
 use serde::{Deserialize, Serialize};
 
 use super::{
@@ -10,42 +30,307 @@ use super::{
     keys::{PublicKey,PrivateKey},
     transaction::{TransactionSpec,InputSpec, OutputSpec},
     block::Block,
+    mempool::{Mempool, TransactionWithFee},
+    hd::{ExtendedPrivateKey, ExtendedPublicKey, KeyChain, HARDENED},
+    keystore::{self, WalletSecrets},
+    coin_selection::{Candidate, CoinSelection, DUST_LIMIT, Selection},
+    history::{Direction, History, HistoryEntry, HistoryView},
+    invoice::{Invoice, InvoiceView, Invoices},
+    address::Address,
+    psbt::Psbt,
+    message,
+    fees::{MIN_FEE_RATE, fee_for_rate, fee_rate},
 };
 
+use crate::utils::get_timestamp;
+
+
 #[derive(Debug, Clone)]
 pub struct Wallet{
+    name: String,
     utxos: HashMap<(Vec<u8>, usize), TxOutput>,
     funds: usize,
-    public_key: PublicKey,
-    private_key: PrivateKey
+    receive: KeyChain,
+    change: KeyChain,
+    unlocked: Option<UnlockedAccount>,
+    pending_spent: HashSet<(Vec<u8>, usize)>,
+    pending_utxos: HashMap<(Vec<u8>, usize), TxOutput>,
+    history: History,
+    invoices: Invoices,
+    imported: HashMap<Vec<u8>, PublicKey>,
+    watched: HashSet<Vec<u8>>,
+    watch_utxos: HashMap<(Vec<u8>, usize), TxOutput>,
+    frozen: HashSet<(Vec<u8>, usize)>,
+    //inputs of partially signed transactions not yet broadcast, sync_mempool leaves these alone
+    reserved: HashSet<(Vec<u8>, usize)>,
+    //receive address block templates pay, kept until a block pays it
+    mining_key: Option<PublicKey>,
+}
+
+//decrypted key file contents, opened away from the node lock and handed to the wallet
+pub struct OpenSecrets{
+    secrets: WalletSecrets,
+    key: [u8; 32],
+}
+
+#[derive(Debug, Clone)]
+struct UnlockedAccount{
+    account: ExtendedPrivateKey,
+    imported: HashMap<Vec<u8>, PrivateKey>,
+    until: Option<Instant>,
+}
+
+//account m/0'
+fn derive_account(mnemonic: &Mnemonic) -> Result<ExtendedPrivateKey>{
+    ExtendedPrivateKey::from_seed(&mnemonic.to_seed(""))?.derive_child(HARDENED)
 }
 
 impl Wallet{
-    pub fn new() -> Self{
-        let private_key = PrivateKey::new();
-        let public_key = private_key.get_public_key();
+    //the recovery phrase is handed back once for the caller to show, it is never logged
+    pub fn new(name: &str, passphrase: &str) -> Result<(Self, String)>{
+        let mut entropy = [0u8; 16];
+        rand::rng().fill_bytes(&mut entropy);
+        let mnemonic = Mnemonic::from_entropy(&entropy)?;
+        let phrase = mnemonic.to_string();
 
-        Self{
-            utxos: HashMap::new(),
-            funds: 0,
-            public_key,
-            private_key,
+        Ok((Self::from_new_mnemonic(name, mnemonic, passphrase)?, phrase))
+    }
+
+    pub fn from_mnemonic(name: &str, phrase: &str, passphrase: &str) -> Result<Self>{
+        Self::from_new_mnemonic(name, Mnemonic::parse(phrase)?, passphrase)
+    }
+
+    fn from_new_mnemonic(name: &str, mnemonic: Mnemonic, passphrase: &str) -> Result<Self>{
+        if passphrase.is_empty(){
+            warn!("Wallet secrets are encrypted with an empty passphrase");
         }
+        let secrets = WalletSecrets { 
+            mnemonic: mnemonic.to_string(), 
+            imported_keys: Vec::new() 
+        };
+        keystore::save(name, &secrets, passphrase)?;
+        keystore::forget_key(name);
+
+        let account = derive_account(&mnemonic)?;
+
+        let mut wallet = Self::from_parts(
+            account.derive_child(RECEIVE_CHAIN)?.to_extended_public_key(), 
+            account.derive_child(CHANGE_CHAIN)?.to_extended_public_key(), 
+            HashMap::new(), 
+            0, 
+            0, 
+            0,
+            History::default()
+        );
+        wallet.name = name.to_string();
+        Ok(wallet)
     }
 
-    fn from_serde_wallet(serde_wallet: &SerdeWallet, utxos: HashMap<(Vec<u8>, usize), TxOutput>) -> Self{
+    fn from_parts(
+        receive_xpub: ExtendedPublicKey,
+        change_xpub: ExtendedPublicKey,
+        utxos: HashMap<(Vec<u8>, usize), TxOutput>,
+        funds: usize,
+        receive_index: u32,
+        change_index: u32,
+        history: History,
+    ) -> Self{
         Self { 
+            name: DEFAULT_WALLET.to_string(),
             utxos, 
-            funds: serde_wallet.funds, 
-            public_key: serde_wallet.public_key.clone(), 
-            private_key: serde_wallet.private_key.clone() 
+            funds, 
+            receive: KeyChain::new(receive_xpub, receive_index),
+            change: KeyChain::new(change_xpub, change_index),
+            unlocked: None,
+            pending_spent: HashSet::new(),
+            pending_utxos: HashMap::new(),
+            history,
+            invoices: Invoices::default(),
+            imported: HashMap::new(),
+            watched: HashSet::new(),
+            watch_utxos: HashMap::new(),
+            frozen: HashSet::new(),
+            reserved: HashSet::new(),
+            mining_key: None,
+        }
+    }
+
+    fn check_secrets(&self, secrets: &WalletSecrets) -> Result<ExtendedPrivateKey>{
+        let account = derive_account(&Mnemonic::parse(&secrets.mnemonic)?)?;
+        if account.derive_child(RECEIVE_CHAIN)?.get_private_key().get_public_key().to_vec()
+            != self.receive.get_xpub().get_public_key().to_vec(){
+            return Err(anyhow!("Key file does not belong to this wallet"))
+        }
+        Ok(account)
+    }
+
+    //no passphrase uses the key remembered in the OS keyring, slow enough to keep off the node lock
+    pub fn open_secrets(name: &str, passphrase: Option<&str>, remember: bool) -> Result<OpenSecrets>{
+        let (secrets, key) = keystore::open(name, passphrase)?;
+        if remember && passphrase.is_some(){
+            keystore::remember_key(name, &key)?;
         }
+        Ok(OpenSecrets { secrets, key })
+    }
+
+    pub fn unlock(&mut self, passphrase: Option<&str>, timeout: Option<u64>, remember: bool) -> Result<()>{
+        let secrets = Self::open_secrets(&self.name, passphrase, remember)?;
+        self.unlock_with(secrets, timeout)
+    }
+
+    pub fn unlock_with(&mut self, secrets: OpenSecrets, timeout: Option<u64>) -> Result<()>{
+        let mut unlocked = self.open_account(&secrets)?;
+        unlocked.until = timeout.map(|secs| Instant::now() + Duration::from_secs(secs));
+        self.unlocked = Some(unlocked);
+        info!("Wallet unlocked: {}", self.name);
+        Ok(())
+    }
+
+    fn open_account(&self, OpenSecrets{secrets, key: _}: &OpenSecrets) -> Result<UnlockedAccount>{
+        let account = self.check_secrets(secrets)?;
+
+        let imported = secrets.imported_keys.iter().map(|key| {
+            let private_key = PrivateKey::from_hex(key.clone())?;
+            Ok((private_key.get_public_key().to_hash(), private_key))
+        }).collect::<Result<HashMap<_, _>>>()?;
+
+        Ok(UnlockedAccount { account, imported, until: None })
+    }
+
+    pub fn lock(&mut self){
+        self.unlocked = None;
+    }
+
+    pub fn forget_remembered_key(&self){
+        keystore::forget_key(&self.name);
+    }
+
+    //a key file means the name is taken, even if the wallet is not loaded
+    pub fn exists(name: &str) -> bool{
+        keystore::exists(name)
+    }
+
+    pub fn get_name(&self) -> &str{
+        &self.name
+    }
+
+    pub fn is_locked(&self) -> bool{
+        match &self.unlocked{
+            Some(unlocked) => unlocked.until.is_some_and(|until| Instant::now() >= until),
+            None => true,
+        }
+    }
+
+    pub fn relock_if_expired(&mut self){
+        if self.unlocked.is_some() && self.is_locked(){
+            info!("Wallet relocked: {}", self.name);
+            self.lock();
+        }
+    }
+
+    //the key is added to the encrypted key file, watched outputs it unlocks become spendable
+    pub fn import_key(&mut self, private_key: &str, passphrase: Option<&str>) -> Result<PublicKey>{
+        let secrets = Self::open_secrets(&self.name, passphrase, false)?;
+        self.import_key_with(private_key, secrets)
+    }
+
+    pub fn import_key_with(&mut self, private_key: &str, OpenSecrets{mut secrets, key}: OpenSecrets) -> Result<PublicKey>{
+        let private_key = PrivateKey::from_hex(private_key.to_string())?;
+        let public_key = private_key.get_public_key();
+        let public_key_hash = public_key.to_hash();
+
+        if self.is_mine_hash(&public_key_hash){
+            return Err(anyhow!("Key already belongs to this wallet"))
+        }
+
+        self.check_secrets(&secrets)?;
+        secrets.imported_keys.push(private_key.to_hex());
+        keystore::update(&self.name, &secrets, &key)?;
+
+        if self.watched.remove(&public_key_hash){
+            let outpoints: Vec<(Vec<u8>, usize)> = self.watch_utxos.iter()
+                .filter(|(_outpoint, utxo)| 
+                    utxo.locking_script.P2PKHLocking_get_public_key_hash().as_ref() == Some(&public_key_hash))
+                .map(|(outpoint, _utxo)| outpoint.clone())
+                .collect();
+            for (hash, index) in outpoints{
+                if let Some(utxo) = self.watch_utxos.remove(&(hash.clone(), index)){
+                    self.insert(hash, index, utxo);
+                }
+            }
+        }
+
+        if let Some(unlocked) = self.unlocked.as_mut(){
+            unlocked.imported.insert(public_key_hash.clone(), private_key);
+        }
+        self.imported.insert(public_key_hash, public_key.clone());
+        info!("Imported key for: {}", public_key.to_address());
+        Ok(public_key)
+    }
+
+    //takes secrets opened with the passphrase rather than trusting an unlocked wallet, returns the key as hex
+    pub fn export_key_with(&self, address: &Address, secrets: &OpenSecrets) -> Result<String>{
+        let unlocked = self.open_account(secrets)?;
+        self.derive_private_key(&unlocked, &address.get_public_key_hash())
+            .map(|private_key| private_key.to_hex())
+            .ok_or(anyhow!("Key does not belong to this wallet"))
+    }
+
+    //signs with the unlocked wallet without going through the key export path
+    pub fn sign_message(&self, address: &Address, message: &str) -> Result<String>{
+        if self.is_locked(){
+            return Err(WalletError::Locked.into())
+        }
+        let private_key = self.get_private_key(&address.get_public_key_hash())
+            .ok_or(anyhow!("Key does not belong to this wallet"))?;
+        Ok(message::sign_message(&private_key, message))
+    }
+
+    pub fn watch(&mut self, address: &Address) -> Result<()>{
+        let public_key_hash = address.get_public_key_hash();
+        if self.is_mine_hash(&public_key_hash){
+            return Err(anyhow!("Key already belongs to this wallet"))
+        }
+        if !self.watched.insert(public_key_hash){
+            return Err(anyhow!("Already watching: {}", address))
+        }
+        info!("Watching: {}", address);
+        Ok(())
+    }
+
+    pub fn get_imported_keys(&self) -> Vec<Address>{
+        self.imported.values().map(|public_key| public_key.to_address()).collect()
+    }
+
+    //watched hashes always have a valid length
+    pub fn get_watched(&self) -> Vec<Address>{
+        self.watched.iter()
+            .filter_map(|public_key_hash| Address::from_public_key_hash(public_key_hash.clone()).ok())
+            .collect()
+    }
+
+    pub fn get_watch_only_funds(&self) -> usize{
+        self.watch_utxos.values().map(|utxo| utxo.value).sum()
     }
 
     pub fn get_funds(&self) -> usize{
         self.funds
     }
 
+    pub fn get_pending_funds(&self) -> usize{
+        self.pending_utxos.iter()
+            .filter(|(outpoint, _utxo)| !self.pending_spent.contains(*outpoint))
+            .map(|(_outpoint, utxo)| utxo.value)
+            .sum()
+    }
+
+    pub fn get_available_funds(&self) -> usize{
+        self.utxos.iter()
+            .filter(|(outpoint, _utxo)| !self.pending_spent.contains(*outpoint) && !self.reserved.contains(*outpoint))
+            .map(|(_outpoint, utxo)| utxo.value)
+            .sum()
+    }
+
     fn insert(&mut self, hash: Vec<u8>, index: usize, utxo: TxOutput){
         self.funds += utxo.value;
         self.utxos.insert((hash, index), utxo);
@@ -58,89 +343,845 @@ impl Wallet{
         }
     }
 
-    pub fn get_public_key(&self) -> PublicKey{
-        self.public_key.clone()
+    pub fn get_receive_key(&self) -> PublicKey{
+        self.receive.current_key()
+    }
+
+    pub fn get_new_address(&mut self) -> PublicKey{
+        self.receive.next_key()
+    }
+
+    //taken off the receive chain so it is never handed out for payments, templates that are
+    //never mined share it so they do not run the chain past the gap limit
+    pub fn get_mining_key(&mut self) -> PublicKey{
+        match &self.mining_key{
+            Some(key) => key.clone(),
+            None => {
+                let key = self.get_new_address();
+                self.mining_key = Some(key.clone());
+                key
+            }
+        }
+    }
+
+    fn mark_used(&mut self, public_key_hash: &Vec<u8>) -> bool{
+        if let Some(index) = self.receive.get_index(public_key_hash){
+            self.receive.mark_used(index);
+            true
+        }else if let Some(index) = self.change.get_index(public_key_hash){
+            self.change.mark_used(index);
+            true
+        }else{
+            self.imported.contains_key(public_key_hash)
+        }
+    }
+
+    fn get_private_key(&self, public_key_hash: &Vec<u8>) -> Option<PrivateKey>{
+        if self.is_locked(){
+            return None
+        }
+        self.derive_private_key(self.unlocked.as_ref()?, public_key_hash)
+    }
+
+    fn derive_private_key(&self, unlocked: &UnlockedAccount, public_key_hash: &Vec<u8>) -> Option<PrivateKey>{
+        if self.imported.contains_key(public_key_hash){
+            return unlocked.imported.get(public_key_hash).cloned()
+        }
+
+        let (chain, index) = if let Some(index) = self.receive.get_index(public_key_hash){
+            (RECEIVE_CHAIN, index)
+        }else{
+            (CHANGE_CHAIN, self.change.get_index(public_key_hash)?)
+        };
+
+        match unlocked.account.derive_path(&[chain, index]){
+            Ok(key) => Some(key.get_private_key()),
+            Err(e) => {
+                warn!("Unable to derive private key: {}", e);
+                None
+            }
+        }
     }
 
     fn add_transaction(&mut self, tx: Transaction){
         let hash = tx.get_hash();
 
         for input in tx.inputs{
+            let outpoint = (input.prev.clone(), input.output_index);
+            self.watch_utxos.remove(&outpoint);
+            self.reserved.remove(&outpoint);
             self.remove(input.prev, input.output_index);
         }
 
         for (index, output) in tx.outputs.iter().enumerate(){
-            if output.locking_script.P2PKHLocking_get_public_key_hash().unwrap() 
-                == self.public_key.to_hash(){
+            let Some(public_key_hash) = output.locking_script.P2PKHLocking_get_public_key_hash() else{
+                continue
+            };
+            if self.mark_used(&public_key_hash){
                 self.insert(hash.clone(), index, output.clone());
+            }else if self.watched.contains(&public_key_hash){
+                self.watch_utxos.insert((hash.clone(), index), output.clone());
             }
         }
     }
 
+    fn is_mine_hash(&self, public_key_hash: &Vec<u8>) -> bool{
+        self.receive.get_index(public_key_hash).is_some() 
+            || self.change.get_index(public_key_hash).is_some()
+            || self.imported.contains_key(public_key_hash)
+    }
+
+    fn is_mine(&self, output: &TxOutput) -> bool{
+        output.locking_script.P2PKHLocking_get_public_key_hash()
+            .is_some_and(|public_key_hash| self.is_mine_hash(&public_key_hash))
+    }
+
+    fn is_watched(&self, output: &TxOutput) -> bool{
+        output.locking_script.P2PKHLocking_get_public_key_hash()
+            .is_some_and(|public_key_hash| self.watched.contains(&public_key_hash))
+    }
+
+    fn owns(&self, output: &TxOutput, watch_only: bool) -> bool{
+        if watch_only {self.is_watched(output)} else {self.is_mine(output)}
+    }
+
+    //must run before the transaction is applied so spent utxo values are still known
+    fn record_transaction(&mut self, tx: &Transaction, block: &Block, watch_only: bool) -> usize{
+        let Some((fee_output, outputs)) = tx.outputs.split_first() else{
+            return 0
+        };
+        let mined = if self.owns(fee_output, watch_only) {fee_output.value} else {0};
+
+        let utxos = if watch_only {&self.watch_utxos} else {&self.utxos};
+        let spent: usize = tx.inputs.iter()
+            .filter_map(|input| utxos.get(&(input.prev.clone(), input.output_index)))
+            .map(|utxo| utxo.value)
+            .sum();
+        let received: usize = outputs.iter()
+            .filter(|output| self.owns(output, watch_only))
+            .map(|output| output.value)
+            .sum();
+        let external: Vec<&TxOutput> = outputs.iter()
+            .filter(|output| !self.owns(output, watch_only))
+            .collect();
+
+        let (direction, amount, fee, counterparties) = if spent > 0{
+            let counterparties = external.iter()
+                .filter_map(|output| output.locking_script.P2PKHLocking_get_public_key_hash())
+                .filter_map(|public_key_hash| Address::from_public_key_hash(public_key_hash).ok())
+                .map(|address| address.to_string())
+                .collect();
+
+            if external.is_empty(){
+                (Direction::SelfTransfer, received, fee_output.value, counterparties)
+            }else{
+                let sent = external.iter().map(|output| output.value).sum();
+                (Direction::Sent, sent, fee_output.value, counterparties)
+            }
+        }else if received > 0{
+            let counterparties = tx.inputs.iter()
+                .filter_map(|input| input.unlocking_script.P2PKHUnlocking_get_public_key())
+                .filter_map(|public_key| PublicKey::from_bytes(public_key).ok())
+                .map(|public_key| public_key.to_address().to_string())
+                .collect();
+            (Direction::Received, received, 0, counterparties)
+        }else{
+            return mined
+        };
+
+        self.history.push(HistoryEntry {
+            txid: hex::encode(tx.get_hash()),
+            direction,
+            amount,
+            fee,
+            counterparties,
+            height: block.get_height(),
+            timestamp: block.get_timestamp(),
+            label: None,
+            watch_only,
+        });
+        mined
+    }
+
     pub fn add_block(&mut self, block: &Block){
-        for transaction in block.get_transactions(){
-            self.add_transaction(transaction);
+        let transactions = block.get_transactions();
+        let mut mined = [0, 0];
+
+        for (position, transaction) in transactions.iter().enumerate(){
+            for watch_only in [false, true]{
+                //fees are paid to the miner as the first output of each transaction
+                mined[watch_only as usize] += if position == 0{
+                    transaction.outputs.iter()
+                        .filter(|output| self.owns(output, watch_only))
+                        .map(|output| output.value)
+                        .sum::<usize>()
+                }else{
+                    self.record_transaction(transaction, block, watch_only)
+                };
+            }
+            self.record_invoice_payments(transaction, position == 0, block);
+            self.add_transaction(transaction.clone());
+        }
+
+        let Some(coinbase) = transactions.first() else{
+            return
+        };
+        if let Some(key) = &self.mining_key 
+            && coinbase.outputs.iter().any(|output| output.locking_script.P2PKHLocking_get_public_key_hash() == Some(key.to_hash())){
+            self.mining_key = None;
+        }
+        for watch_only in [false, true]{
+            if mined[watch_only as usize] > 0{
+                self.history.push(HistoryEntry {
+                    txid: hex::encode(coinbase.get_hash()),
+                    direction: Direction::Mined,
+                    amount: mined[watch_only as usize],
+                    fee: 0,
+                    counterparties: vec![],
+                    height: block.get_height(),
+                    timestamp: block.get_timestamp(),
+                    label: None,
+                    watch_only,
+                });
+            }
+        }
+    }
+
+    //the first output of a non-coinbase transaction is the miner fee, never an invoice payment
+    fn record_invoice_payments(&mut self, tx: &Transaction, coinbase: bool, block: &Block){
+        let txid = hex::encode(tx.get_hash());
+        for (index, output) in tx.outputs.iter().enumerate().skip(if coinbase {0} else {1}){
+            if let Some(public_key_hash) = output.locking_script.P2PKHLocking_get_public_key_hash(){
+                self.invoices.record_payment(&public_key_hash, &txid, index, output.value, block.get_height());
+            }
+        }
+    }
+
+    pub fn start_rescan(&mut self, from_height: usize){
+        self.utxos.clear();
+        self.watch_utxos.clear();
+        self.funds = 0;
+        self.history.remove_from(from_height);
+        self.invoices.remove_from(from_height);
+    }
+
+    //blocks below the rescan height only rebuild utxos, history before it is kept
+    pub fn rescan_block(&mut self, block: &Block, from_height: usize){
+        if block.get_height() >= from_height{
+            self.add_block(block);
+        }else{
+            for transaction in block.get_transactions(){
+                self.add_transaction(transaction);
+            }
+        }
+    }
+
+    pub fn finish_rescan(&mut self, rescanned: Wallet){
+        let labels = self.history.get_labels();
+
+        self.utxos = rescanned.utxos;
+        self.funds = rescanned.funds;
+        self.watch_utxos = rescanned.watch_utxos;
+        //addresses handed out while the rescan ran must not be given out again
+        if rescanned.receive.get_next_index() > self.receive.get_next_index(){
+            self.receive = rescanned.receive;
+        }
+        if rescanned.change.get_next_index() > self.change.get_next_index(){
+            self.change = rescanned.change;
+        }
+        self.history = rescanned.history;
+        self.invoices.merge_payments(rescanned.invoices);
+
+        for (txid, label) in labels{
+            self.history.set_label(&txid, Some(label));
+        }
+    }
+
+    pub fn get_history(&self, tip_height: Option<usize>) -> Vec<HistoryView>{
+        self.history.get_views(tip_height)
+    }
+
+    pub fn export_history_csv(&self, tip_height: Option<usize>) -> String{
+        self.history.to_csv(tip_height)
+    }
+
+    pub fn set_label(&mut self, txid: &str, label: Option<String>) -> bool{
+        self.history.set_label(txid, label)
+    }
+
+    //each invoice gets a fresh receive address so payments can be matched to it
+    pub fn create_invoice(
+        &mut self, 
+        amount: Option<usize>, 
+        label: Option<String>, 
+        message: Option<String>, 
+        expiry: Option<usize>
+    ) -> InvoiceView{
+        let mut id = [0u8; 8];
+        rand::rng().fill_bytes(&mut id);
+        let now = get_timestamp();
+
+        let invoice = Invoice {
+            id: hex::encode(id),
+            address: self.get_new_address().to_address(),
+            amount,
+            label,
+            message,
+            created: now,
+            expires: expiry.map(|expiry| now + expiry),
+            payments: Vec::new(),
+        };
+        self.invoices.push(invoice.clone());
+        invoice.to_view(now)
+    }
+
+    pub fn get_invoices(&self) -> Vec<InvoiceView>{
+        self.invoices.get_views(get_timestamp())
+    }
+
+    pub fn get_invoice(&self, id: &str) -> Option<InvoiceView>{
+        self.invoices.get(id).map(|invoice| invoice.to_view(get_timestamp()))
+    }
+
+    pub fn add_pending_transaction(&mut self, tx: &Transaction){
+        let hash = tx.get_hash();
+
+        for input in tx.inputs.iter(){
+            let outpoint = (input.prev.clone(), input.output_index);
+            self.reserved.remove(&outpoint);
+            self.pending_spent.insert(outpoint);
         }
+
+        for (index, output) in tx.outputs.iter().enumerate(){
+            let Some(public_key_hash) = output.locking_script.P2PKHLocking_get_public_key_hash() else{
+                continue
+            };
+            if self.mark_used(&public_key_hash){
+                self.pending_utxos.insert((hash.clone(), index), output.clone());
+            }
+        }
+    }
+
+    pub fn is_involved(&self, tx: &Transaction) -> bool{
+        tx.outputs.iter().any(|output| self.is_mine(output))
+            || tx.inputs.iter().any(|input| {
+                let outpoint = (input.prev.clone(), input.output_index);
+                self.utxos.contains_key(&outpoint) || self.pending_utxos.contains_key(&outpoint)
+            })
+    }
+
+    //pending state is rebuilt from the mempool after blocks confirm or evict transactions
+    pub fn sync_mempool(&mut self, mempool: &Mempool){
+        self.pending_spent.clear();
+        self.pending_utxos.clear();
+        for TransactionWithFee{transaction, ..} in mempool.to_vec(){
+            self.add_pending_transaction(&transaction);
+        }
+    }
+
+    fn get_candidates(&self, watch_only: bool) -> Vec<Candidate>{
+        let utxos = if watch_only {&self.watch_utxos} else {&self.utxos};
+        utxos.iter()
+            .filter(|(outpoint, _utxo)| !self.pending_spent.contains(*outpoint) && !self.frozen.contains(*outpoint))
+            .filter(|(outpoint, _utxo)| !self.reserved.contains(*outpoint))
+            .map(|((hash, index), utxo)| Candidate {
+            hash: hash.clone(),
+            index: *index,
+            value: utxo.value
+        }).collect()
+    }
+
+    fn select(
+        &self, 
+        forced: &[Candidate], 
+        spend: usize, 
+        fee: usize, 
+        strategy: CoinSelection,
+        watch_only: bool,
+        add_inputs: bool,
+    ) -> Result<Selection, WalletError>{
+        let candidates = if add_inputs {self.get_candidates(watch_only)} else {Vec::new()};
+        let available: usize = candidates.iter().chain(forced).map(|input| input.value).sum();
+        strategy.select_with_inputs(forced.to_vec(), candidates, spend + fee, fee).ok_or(
+            WalletError::InsufficientFunds { 
+                required: spend + fee, 
+                available
+            }
+        )
+    }
+
+    //with manual inputs only those outpoints are spent, otherwise the strategy picks them
+    pub fn plan_transaction(
+        &self, 
+        outputs: &[OutputSpec], 
+        fee: Fee, 
+        strategy: CoinSelection,
+        inputs: &[(Vec<u8>, usize)],
+    ) -> Result<TransactionPlan, WalletError>{
+        if inputs.is_empty(){
+            return self.plan_with_inputs(&[], outputs, fee, strategy, false, true)
+        }
+        let forced = self.get_manual_inputs(inputs)?;
+        self.plan_with_inputs(&forced, outputs, fee, strategy, false, false)
+    }
+
+    fn get_manual_inputs(&self, inputs: &[(Vec<u8>, usize)]) -> Result<Vec<Candidate>, WalletError>{
+        let mut seen = HashSet::new();
+        inputs.iter().filter(|outpoint| seen.insert(*outpoint)).map(|outpoint| {
+            let name = format_outpoint(outpoint);
+            if self.frozen.contains(outpoint){
+                return Err(WalletError::UnknownInput(format!("{} is frozen", name)))
+            }
+            if self.pending_spent.contains(outpoint){
+                return Err(WalletError::UnknownInput(format!("{} is already being spent", name)))
+            }
+            if self.reserved.contains(outpoint){
+                return Err(WalletError::UnknownInput(format!("{} is reserved by a partially signed transaction", name)))
+            }
+            if self.pending_utxos.contains_key(outpoint){
+                return Err(WalletError::UnknownInput(format!("{} is not confirmed yet", name)))
+            }
+            self.utxos.get(outpoint)
+                .map(|utxo| Candidate { hash: outpoint.0.clone(), index: outpoint.1, value: utxo.value })
+                .ok_or(WalletError::UnknownInput(format!("{} is not an output of this wallet", name)))
+        }).collect()
+    }
+
+    fn plan_with_inputs(
+        &self, 
+        forced: &[Candidate],
+        outputs: &[OutputSpec], 
+        fee: Fee, 
+        strategy: CoinSelection,
+        watch_only: bool,
+        add_inputs: bool,
+    ) -> Result<TransactionPlan, WalletError>{
+        let spend: usize = outputs.iter().map(|output| output.value).sum();
+
+        let rate = match fee{
+            Fee::Absolute(fee) => {
+                let selection = self.select(forced, spend, fee, strategy, watch_only, add_inputs)?;
+                return Ok(TransactionPlan::from_selection(selection, outputs.len()))
+            }
+            Fee::Rate(rate) => rate,
+        };
+
+        //more inputs make the transaction larger, so reselect until the fee covers the size
+        let mut fee = 0;
+        for _ in 0..MAX_FEE_ROUNDS{
+            let selection = self.select(forced, spend, fee, strategy, watch_only, add_inputs)?;
+            let plan = TransactionPlan::from_selection(selection, outputs.len());
+            let required = fee_for_rate(rate, plan.size);
+            if plan.fee >= required{
+                return Ok(plan)
+            }
+            fee = required;
+        }
+        Ok(TransactionPlan::from_selection(self.select(forced, spend, fee, strategy, watch_only, add_inputs)?, outputs.len()))
+    }
+
+    pub fn new_transaction(
+        &mut self, 
+        version: usize, 
+        outputs: Vec<OutputSpec>, 
+        fee: Fee, 
+        strategy: CoinSelection,
+        replaceable: bool,
+        inputs: &[(Vec<u8>, usize)],
+    ) -> Result<Transaction, WalletError>{
+        if self.is_locked(){
+            return Err(WalletError::Locked)
+        }
+
+        let plan = self.plan_transaction(&outputs, fee, strategy, inputs)?;
+        self.build_transaction(version, outputs, plan, replaceable)
     }
 
-    pub fn new_transaction(&self, version: usize, outputs: Vec<OutputSpec>, fee: usize) -> Transaction{
+    //inputs are confirmed outputs of this wallet, a pending output is keyed by a txid that changes when it is mined
+    fn build_transaction(
+        &mut self,
+        version: usize, 
+        outputs: Vec<OutputSpec>, 
+        plan: TransactionPlan,
+        replaceable: bool,
+    ) -> Result<Transaction, WalletError>{
         let mut tx_spec = TransactionSpec::pre_inputs(
             version, 
             outputs, 
-            self.public_key.clone(), 
-            self.private_key.clone()
+            replaceable,
         );
-        
-        let spend: usize = tx_spec.outputs.iter().map(|utxo| utxo.value).sum();
-
-        let mut utxos = self.utxos.clone();
-        let mut spent: usize = 0;
-
-        while spent < spend + fee{
-            if let Some((hash, index)) = utxos.keys().next().cloned(){
-                
-                let utxo = utxos.remove(&(hash.clone(), index)).unwrap();
-                spent += utxo.value;
-                tx_spec.inputs.push(InputSpec::new(hash, index, utxo));
-                
-            }else{
-                warn!("Insufficient funds");
-                panic!();
-            }
+
+        for input in plan.selection.inputs.iter(){
+            let outpoint = (input.hash.clone(), input.index);
+            let missing_key = || WalletError::MissingKey(format!("{}:{}", hex::encode(&input.hash), input.index));
+
+            let Some(utxo) = self.utxos.get(&outpoint).cloned() else{
+                return Err(missing_key())
+            };
+            let Some(private_key) = utxo.locking_script.P2PKHLocking_get_public_key_hash()
+                .and_then(|public_key_hash| self.get_private_key(&public_key_hash)) else{
+                return Err(missing_key())
+            };
+            tx_spec.inputs.push(InputSpec::new(input.hash.clone(), input.index, utxo, private_key));
         }
-        if spent > (spend + fee){
+
+        if plan.selection.change > 0{
             tx_spec.outputs.push(OutputSpec::new(
-                spent - (fee + spend),
-                self.get_public_key().to_vec())
+                plan.selection.change,
+                self.change.next_key().to_address())
             )
         }
-        
-        tx_spec.to_transaction()
+
+        let transaction = tx_spec.to_transaction();
+        self.add_pending_transaction(&transaction);
+        Ok(transaction)
+    }
+
+    //needs no private keys, so a locked or watch-only wallet can prepare a transaction for an offline signer
+    //the inputs stay reserved until the transaction reaches the mempool or the psbt is abandoned
+    pub fn create_psbt(
+        &mut self, 
+        version: usize, 
+        outputs: Vec<OutputSpec>, 
+        fee: Fee, 
+        strategy: CoinSelection,
+        replaceable: bool,
+        watch_only: bool,
+    ) -> Result<Psbt, WalletError>{
+        let plan = self.plan_with_inputs(&[], &outputs, fee, strategy, watch_only, true)?;
+        let inputs: Vec<(Vec<u8>, usize)> = plan.selection.inputs.iter()
+            .map(|input| (input.hash.clone(), input.index))
+            .collect();
+        let lookup = |hash: &Vec<u8>, index: usize| {
+            let outpoint = (hash.clone(), index);
+            self.utxos.get(&outpoint)
+                .or(self.watch_utxos.get(&outpoint))
+                .cloned()
+        };
+
+        let mut outputs = outputs;
+        let mut change = Vec::new();
+        if plan.selection.change > 0{
+            //watched addresses have no change chain here, so change returns to the first input
+            let address = if watch_only{
+                inputs.first()
+                    .and_then(|(hash, index)| lookup(hash, *index))
+                    .and_then(|utxo| utxo.locking_script.P2PKHLocking_get_public_key_hash())
+                    .and_then(|public_key_hash| Address::from_public_key_hash(public_key_hash).ok())
+                    .ok_or(WalletError::MissingKey("change address".to_string()))?
+            }else{
+                self.change.next_key().to_address()
+            };
+            change.push(outputs.len());
+            outputs.push(OutputSpec::new(plan.selection.change, address));
+        }
+
+        let mut psbt = Psbt::create(Transaction::unsigned(version, inputs, outputs, replaceable))
+            .expect("unsigned transaction has no unlocking scripts");
+        psbt.update(lookup);
+        psbt.set_change(change);
+        self.reserved.extend(psbt.get_outpoints());
+
+        info!("Created partially signed transaction with fee: {}", plan.fee);
+        Ok(psbt)
+    }
+
+    //returns how many reserved inputs were released
+    pub fn abandon_psbt(&mut self, psbt: &Psbt) -> usize{
+        psbt.get_outpoints().iter()
+            .filter(|outpoint| self.reserved.remove(*outpoint))
+            .count()
+    }
+
+    //returns how many inputs were signed
+    pub fn sign_psbt(&self, psbt: &mut Psbt) -> Result<usize, WalletError>{
+        if self.is_locked(){
+            return Err(WalletError::Locked)
+        }
+
+        let mut signed = 0;
+        for public_key_hash in psbt.get_unsigned_hashes(){
+            if let Some(private_key) = self.get_private_key(&public_key_hash){
+                signed += psbt.sign(&private_key);
+            }
+        }
+        Ok(signed)
+    }
+
+    fn is_change(&self, output: &TxOutput) -> bool{
+        output.locking_script.P2PKHLocking_get_public_key_hash()
+            .is_some_and(|public_key_hash| self.change.get_index(&public_key_hash).is_some())
+    }
+
+    //replace-by-fee: same inputs and payments, smaller change, extra inputs only if the change runs out
+    pub fn bump_fee(
+        &mut self, 
+        version: usize, 
+        original: &Transaction, 
+        original_fee: usize, 
+        fee: Fee
+    ) -> Result<Transaction, WalletError>{
+        if self.is_locked(){
+            return Err(WalletError::Locked)
+        }
+        if !original.is_replaceable(){
+            return Err(WalletError::FeeBump("Transaction does not signal replaceability".to_string()))
+        }
+
+        let forced = original.inputs.iter().map(|input| {
+            let outpoint = (input.prev.clone(), input.output_index);
+            self.utxos.get(&outpoint)
+                .map(|utxo| Candidate { hash: input.prev.clone(), index: input.output_index, value: utxo.value })
+                .ok_or(WalletError::FeeBump("Transaction spends outputs of another wallet".to_string()))
+        }).collect::<Result<Vec<Candidate>, WalletError>>()?;
+
+        let outputs = original.outputs.iter()
+            .filter(|output| !self.is_change(output))
+            .map(|output| {
+                output.locking_script.P2PKHLocking_get_public_key_hash()
+                    .and_then(|public_key_hash| Address::from_public_key_hash(public_key_hash).ok())
+                    .map(|address| OutputSpec::new(output.value, address))
+                    .ok_or(WalletError::FeeBump("Transaction has a non standard output".to_string()))
+            }).collect::<Result<Vec<OutputSpec>, WalletError>>()?;
+
+        let mut plan = self.plan_with_inputs(&forced, &outputs, fee, CoinSelection::LargestFirst, false, true)?;
+
+        //mempool replacement rules: pay for the relay of the new transaction and beat the old fee rate
+        let required = (original_fee + fee_for_rate(MIN_FEE_RATE, plan.size)).max(
+            fee_for_rate(fee_rate(original_fee, original.get_size()) + 1, plan.size)
+        );
+        if plan.fee < required{
+            plan = self.plan_with_inputs(&forced, &outputs, Fee::Absolute(required), CoinSelection::LargestFirst, false, true)?;
+        }
+
+        info!("Bumping fee from {} to {}", original_fee, plan.fee);
+        self.build_transaction(version, outputs, plan, true)
+    }
+
+    //sweeps the smallest confirmed outputs below the threshold into one, best done while fees are low
+    pub fn consolidate(
+        &mut self, 
+        version: usize, 
+        threshold: usize, 
+        max_inputs: usize, 
+        rate: usize
+    ) -> Result<Transaction, WalletError>{
+        if self.is_locked(){
+            return Err(WalletError::Locked)
+        }
+
+        let mut inputs: Vec<Candidate> = self.get_candidates(false).into_iter()
+            .filter(|candidate| candidate.value < threshold)
+            .collect();
+        inputs.sort_by_key(|candidate| candidate.value);
+        inputs.truncate(max_inputs);
+        if inputs.len() < MIN_CONSOLIDATION_INPUTS{
+            return Err(WalletError::Consolidate(format!("fewer than {} outputs below {}", MIN_CONSOLIDATION_INPUTS, threshold)))
+        }
+
+        let total: usize = inputs.iter().map(|input| input.value).sum();
+        let fee = fee_for_rate(rate, Transaction::estimate_size(inputs.len(), 1));
+        if total < fee + DUST_LIMIT{
+            return Err(WalletError::InsufficientFunds { required: fee + DUST_LIMIT, available: total })
+        }
+
+        let outputs = vec![OutputSpec::new(total - fee, self.change.next_key().to_address())];
+        let plan = TransactionPlan::from_selection(Selection { inputs, fee, change: 0 }, 1);
+
+        info!("Consolidating {} outputs with fee: {}", plan.inputs.len(), fee);
+        self.build_transaction(version, outputs, plan, true)
+    }
+
+    //confirmations and labels come from the history entry of the transaction that created the output
+    pub fn get_utxos(&self, tip_height: Option<usize>) -> Vec<UtxoView>{
+        let history = self.history.get_views(tip_height);
+        let confirmed = self.utxos.iter().map(|utxo| (utxo, false));
+        let pending = self.pending_utxos.iter().map(|utxo| (utxo, true));
+
+        let mut utxos: Vec<UtxoView> = confirmed.chain(pending)
+            .filter(|((outpoint, _utxo), _pending)| !self.pending_spent.contains(*outpoint))
+            .map(|((outpoint, utxo), pending)| {
+                let txid = hex::encode(&outpoint.0);
+                let entry = history.iter().find(|view| view.entry.txid == txid && !view.entry.watch_only);
+                UtxoView {
+                    outpoint: format_outpoint(outpoint),
+                    value: utxo.value,
+                    address: utxo.locking_script.P2PKHLocking_get_public_key_hash()
+                        .and_then(|public_key_hash| Address::from_public_key_hash(public_key_hash).ok())
+                        .map(|address| address.to_string()),
+                    confirmations: if pending {0} else {entry.map_or(0, |view| view.confirmations)},
+                    label: entry.and_then(|view| view.entry.label.clone()),
+                    frozen: self.frozen.contains(outpoint),
+                    pending,
+                }
+            }).collect();
+        utxos.sort_by_key(|utxo| Reverse(utxo.value));
+        utxos
+    }
+
+    pub fn freeze(&mut self, outpoint: &(Vec<u8>, usize)) -> Result<(), WalletError>{
+        if !self.utxos.contains_key(outpoint) && !self.pending_utxos.contains_key(outpoint){
+            return Err(WalletError::UnknownInput(format!("{} is not an output of this wallet", format_outpoint(outpoint))))
+        }
+        self.frozen.insert(outpoint.clone());
+        Ok(())
+    }
+
+    pub fn unfreeze(&mut self, outpoint: &(Vec<u8>, usize)) -> bool{
+        self.frozen.remove(outpoint)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UtxoView{
+    pub outpoint: String,
+    pub value: usize,
+    pub address: Option<String>,
+    pub confirmations: usize,
+    pub label: Option<String>,
+    pub frozen: bool,
+    pub pending: bool,
+}
+
+#[derive(Debug)]
+pub enum WalletError{
+    NotLoaded(String),
+    Locked,
+    InsufficientFunds{
+        required: usize,
+        available: usize,
+    },
+    MissingKey(String),
+    FeeBump(String),
+    UnknownInput(String),
+    Consolidate(String),
+}
+
+impl fmt::Display for WalletError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            WalletError::NotLoaded(name) => write!(f, "Wallet not loaded: {}", name),
+            WalletError::Locked => write!(f, "Wallet is locked"),
+            WalletError::InsufficientFunds { required, available } => {
+                write!(f, "Insufficient funds: required {}, available {}", required, available)
+            }
+            WalletError::MissingKey(outpoint) => write!(f, "Missing key for utxo: {}", outpoint),
+            WalletError::FeeBump(reason) => write!(f, "Unable to bump fee: {}", reason),
+            WalletError::UnknownInput(reason) => write!(f, "Unable to spend input: {}", reason),
+            WalletError::Consolidate(reason) => write!(f, "Nothing to consolidate: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for WalletError{}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Fee{
+    Absolute(usize),
+    Rate(usize),
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransactionPlan{
+    inputs: Vec<(String, usize)>,
+    fee: usize,
+    change: usize,
+    size: usize,
+    #[serde(skip)]
+    selection: Selection,
+}
+
+impl TransactionPlan{
+    fn from_selection(selection: Selection, output_count: usize) -> Self{
+        let output_count = output_count + if selection.change > 0 {1} else {0};
+        Self { 
+            inputs: selection.inputs.iter().map(
+                |input| 
+                (format!("{}:{}", hex::encode(&input.hash), input.index), input.value)
+            ).collect(), 
+            fee: selection.fee, 
+            change: selection.change, 
+            size: Transaction::estimate_size(selection.inputs.len(), output_count),
+            selection 
+        }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 struct SerdeWallet{
+    #[serde(default = "default_name")]
+    pub name: String,
     pub utxos: HashMap<String, TxOutput>,
     pub funds: usize,
-    pub public_key: PublicKey,
-    pub private_key: PrivateKey,
+    pub receive_xpub: ExtendedPublicKey,
+    pub change_xpub: ExtendedPublicKey,
+    pub receive_index: u32,
+    pub change_index: u32,
+    #[serde(default)]
+    pub history: History,
+    #[serde(default)]
+    pub invoices: Invoices,
+    #[serde(default)]
+    pub imported: Vec<PublicKey>,
+    #[serde(default)]
+    pub watched: Vec<Address>,
+    #[serde(default)]
+    pub watch_utxos: HashMap<String, TxOutput>,
+    #[serde(default)]
+    pub frozen: Vec<String>,
+    #[serde(default)]
+    pub reserved: Vec<String>,
+    #[serde(default)]
+    pub mining_key: Option<PublicKey>,
+}
+
+fn default_name() -> String{
+    DEFAULT_WALLET.to_string()
+}
+
+fn format_outpoint((hash, index): &(Vec<u8>, usize)) -> String{
+    format!("{}:{}", hex::encode(hash), index)
+}
+
+//outpoints are written as txid:index
+pub fn parse_outpoint(outpoint: &str) -> Result<(Vec<u8>, usize)>{
+    let (hash, index) = outpoint.trim().split_once(":").ok_or(anyhow!("No colon found in outpoint: {}", outpoint))?;
+    Ok((hex::decode(hash)?, index.parse()?))
+}
+
+fn serialize_utxos(utxos: &HashMap<(Vec<u8>, usize), TxOutput>) -> HashMap<String, TxOutput>{
+    utxos.iter().map(
+        |(outpoint, output)|
+        (format_outpoint(outpoint), output.clone())
+    ).collect()
+}
+
+fn deserialize_utxos(utxos: HashMap<String, TxOutput>) -> Result<HashMap<(Vec<u8>, usize), TxOutput>>{
+    utxos.into_iter().map(
+        |(key, output)| Ok((parse_outpoint(&key)?, output))
+    ).collect()
 }
 
 impl Serialize for Wallet{
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
             S: serde::Serializer {
-        let serde_utxos: HashMap<String, TxOutput> = self.utxos.iter().map(
-            |((hash, index), output)|
-            (format!("{}:{}", hex::encode(hash.clone()), index.clone()), output.clone())
-        ).collect();
-        
         let serde_wallet = SerdeWallet{
-            utxos: serde_utxos,
+            name: self.name.clone(),
+            utxos: serialize_utxos(&self.utxos),
             funds: self.funds,
-            public_key: self.public_key.clone(),
-            private_key: self.private_key.clone()
+            receive_xpub: self.receive.get_xpub(),
+            change_xpub: self.change.get_xpub(),
+            receive_index: self.receive.get_next_index(),
+            change_index: self.change.get_next_index(),
+            history: self.history.clone(),
+            invoices: self.invoices.clone(),
+            imported: self.imported.values().cloned().collect(),
+            watched: self.get_watched(),
+            watch_utxos: serialize_utxos(&self.watch_utxos),
+            frozen: self.frozen.iter().map(format_outpoint).collect(),
+            reserved: self.reserved.iter().map(format_outpoint).collect(),
+            mining_key: self.mining_key.clone(),
         };
 
         serde_wallet.serialize(serializer)
@@ -152,19 +1193,191 @@ impl <'de>Deserialize<'de> for Wallet{
         where
             D: serde::Deserializer<'de> {
         let serde_wallet = SerdeWallet::deserialize(deserializer)?;
-        
-        let utxos = serde_wallet.utxos.iter().map(
-            |(key, output)|{
+        use serde::de::Error;
 
-            let (hash, index) = key.split_once(":").expect("No colon found for utxos key"); 
-            let hash = hex::decode(&hash).map_err(serde::de::Error::custom)?;
-            let index: usize = index.parse().expect("index not a number");
-            Ok(((hash, index.clone()), output.clone()))
-            }
-            
-        ).collect::<Result<HashMap<_, _>, _>>()?;
+        let utxos = deserialize_utxos(serde_wallet.utxos).map_err(D::Error::custom)?;
+
+        let mut wallet = Wallet::from_parts(
+            serde_wallet.receive_xpub, 
+            serde_wallet.change_xpub, 
+            utxos, 
+            serde_wallet.funds, 
+            serde_wallet.receive_index, 
+            serde_wallet.change_index,
+            serde_wallet.history
+        );
+
+        wallet.name = serde_wallet.name;
+        wallet.invoices = serde_wallet.invoices;
+        wallet.imported = serde_wallet.imported.into_iter()
+            .map(|public_key| (public_key.to_hash(), public_key))
+            .collect();
+        wallet.watched = serde_wallet.watched.iter()
+            .map(|address| address.get_public_key_hash())
+            .collect();
+        wallet.watch_utxos = deserialize_utxos(serde_wallet.watch_utxos).map_err(D::Error::custom)?;
+        wallet.frozen = serde_wallet.frozen.iter()
+            .map(|outpoint| parse_outpoint(outpoint))
+            .collect::<Result<_>>()
+            .map_err(D::Error::custom)?;
+        wallet.reserved = serde_wallet.reserved.iter()
+            .map(|outpoint| parse_outpoint(outpoint))
+            .collect::<Result<_>>()
+            .map_err(D::Error::custom)?;
+        wallet.mining_key = serde_wallet.mining_key;
+        Ok(wallet)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn unlocked_wallet() -> Wallet{
+        let account = derive_account(&Mnemonic::from_entropy(&[7; 16]).unwrap()).unwrap();
+        let mut wallet = Wallet::from_parts(
+            account.derive_child(RECEIVE_CHAIN).unwrap().to_extended_public_key(), 
+            account.derive_child(CHANGE_CHAIN).unwrap().to_extended_public_key(), 
+            HashMap::new(), 
+            0, 
+            0, 
+            0,
+            History::default()
+        );
+        wallet.unlocked = Some(UnlockedAccount { account, imported: HashMap::new(), until: None });
+        wallet
+    }
+
+    //one block per value, each mined to a fresh receive address
+    fn fund(wallet: &mut Wallet, values: &[usize]) -> Vec<Block>{
+        values.iter().enumerate().map(|(height, value)| {
+            let coinbase = Transaction::reward(*value, wallet.get_new_address(), 0);
+            let block = Block::new(height, 1, 0, vec![coinbase], vec![0; 32]);
+            wallet.add_block(&block);
+            block
+        }).collect()
+    }
+
+    fn payee() -> Address{
+        PrivateKey::new().get_public_key().to_address()
+    }
+
+    #[test]
+    fn dry_run_plans_without_spending(){
+        let mut wallet = unlocked_wallet();
+        fund(&mut wallet, &[1_000, 500]);
+
+        let outputs = [OutputSpec::new(600, payee())];
+        let plan = wallet.plan_transaction(&outputs, Fee::Absolute(10), CoinSelection::LargestFirst, &[]).unwrap();
+        assert_eq!(plan.inputs.len(), 1);
+        assert_eq!(plan.inputs[0].1, 1_000);
+        assert_eq!((plan.fee, plan.change), (10, 390));
+        assert_eq!(wallet.get_available_funds(), 1_500);
+    }
+
+    #[test]
+    fn dust_change_goes_to_the_fee(){
+        let mut wallet = unlocked_wallet();
+        fund(&mut wallet, &[1_000]);
 
+        let outputs = [OutputSpec::new(1_000 - 10 - (DUST_LIMIT - 1), payee())];
+        let plan = wallet.plan_transaction(&outputs, Fee::Absolute(10), CoinSelection::LargestFirst, &[]).unwrap();
+        assert_eq!((plan.fee, plan.change), (10 + DUST_LIMIT - 1, 0));
+    }
+
+    #[test]
+    fn insufficient_funds_is_an_error(){
+        let mut wallet = unlocked_wallet();
+        fund(&mut wallet, &[1_000]);
+
+        let outputs = vec![OutputSpec::new(2_000, payee())];
+        let error = wallet.new_transaction(0, outputs, Fee::Absolute(10), CoinSelection::LargestFirst, false, &[]).unwrap_err();
+        assert!(matches!(error, WalletError::InsufficientFunds { required: 2_010, available: 1_000 }));
+        assert_eq!(wallet.get_available_funds(), 1_000);
+    }
+
+    #[test]
+    fn pending_spends_are_reserved_until_released(){
+        let mut wallet = unlocked_wallet();
+        fund(&mut wallet, &[1_000]);
+
+        let payee = payee();
+        let outputs = vec![OutputSpec::new(600, payee.clone())];
+        wallet.new_transaction(0, outputs, Fee::Absolute(10), CoinSelection::LargestFirst, false, &[]).unwrap();
+        assert_eq!(wallet.get_funds(), 1_000);
+        assert_eq!(wallet.get_available_funds(), 0);
+        assert_eq!(wallet.get_pending_funds(), 390);
+
+        //the transaction left the mempool without confirming
+        wallet.sync_mempool(&Mempool::new());
+        assert_eq!(wallet.get_available_funds(), 1_000);
+        assert_eq!(wallet.get_pending_funds(), 0);
+
+        let outputs = vec![OutputSpec::new(600, payee)];
+        let psbt = wallet.create_psbt(0, outputs, Fee::Absolute(10), CoinSelection::LargestFirst, false, false).unwrap();
+        assert_eq!(wallet.get_available_funds(), 0);
+        wallet.sync_mempool(&Mempool::new());
+        assert_eq!(wallet.get_available_funds(), 0);
+        assert_eq!(wallet.abandon_psbt(&psbt), 1);
+        assert_eq!(wallet.get_available_funds(), 1_000);
+    }
+
+    #[test]
+    fn history_records_sends_with_confirmations_and_exports_csv(){
+        let mut wallet = unlocked_wallet();
+        fund(&mut wallet, &[1_000]);
+        let payee = payee();
 
-        Ok(Wallet::from_serde_wallet(&serde_wallet, utxos))
+        let outputs = vec![OutputSpec::new(600, payee.clone())];
+        let mut mined = wallet.new_transaction(0, outputs, Fee::Absolute(10), CoinSelection::LargestFirst, false, &[]).unwrap();
+        mined.add_fee(PrivateKey::new().get_public_key(), 10);
+        let coinbase = Transaction::reward(1, PrivateKey::new().get_public_key(), 0);
+        wallet.add_block(&Block::new(1, 1, 0, vec![coinbase, mined.clone()], vec![0; 32]));
+
+        let txid = hex::encode(mined.get_hash());
+        assert!(wallet.set_label(&txid, Some("rent, march".to_string())));
+
+        let history = wallet.get_history(Some(1));
+        assert_eq!(history.len(), 2);
+        let sent = history.iter().find(|view| view.entry.txid == txid).unwrap();
+        assert!(matches!(sent.entry.direction, Direction::Sent));
+        assert_eq!((sent.entry.amount, sent.entry.fee, sent.confirmations), (600, 10, 1));
+        assert_eq!(sent.entry.counterparties, vec![payee.to_string()]);
+        let mined_reward = history.iter().find(|view| matches!(view.entry.direction, Direction::Mined)).unwrap();
+        assert_eq!((mined_reward.entry.amount, mined_reward.confirmations), (1_000, 2));
+
+        let csv = wallet.export_history_csv(Some(1));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("txid,direction,amount,fee"));
+        let row = lines.iter().find(|line| line.starts_with(&txid)).unwrap();
+        assert!(row.contains(",sent,600,10,"));
+        assert!(row.contains("\"rent, march\""));
+    }
+
+    //replayed the way rescan_wallet does it, on a copy that replaces the wallet's chain state at the end
+    #[test]
+    fn rescan_rebuilds_utxos_and_keeps_history_and_labels(){
+        let mut wallet = unlocked_wallet();
+        let blocks = fund(&mut wallet, &[1_000, 500]);
+        let txid = hex::encode(blocks[0].get_transactions()[0].get_hash());
+        assert!(wallet.set_label(&txid, Some("first reward".to_string())));
+
+        wallet.utxos.clear();
+        wallet.funds = 0;
+
+        let mut rescanned = wallet.clone();
+        rescanned.start_rescan(1);
+        for block in blocks.iter(){
+            rescanned.rescan_block(block, 1);
+        }
+        wallet.finish_rescan(rescanned);
+
+        assert_eq!(wallet.get_funds(), 1_500);
+        assert_eq!(wallet.get_utxos(Some(1)).len(), 2);
+        let history = wallet.get_history(Some(1));
+        assert_eq!(history.len(), 2);
+        let first = history.iter().find(|view| view.entry.txid == txid).unwrap();
+        assert_eq!(first.entry.label.as_deref(), Some("first reward"));
     }
-}
\ No newline at end of file
+}
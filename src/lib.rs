@@ -2,7 +2,7 @@
 
 mod node;
 pub mod block;
-mod mine;
+pub mod mine;
 mod network;
 mod ui;
 mod utils;
@@ -1,3 +1,5 @@
 mod server;
+mod stratum;
 
-pub use server::start_mining_server;
\ No newline at end of file
+pub use server::start_mining_server;
+pub use stratum::{STRATUM_PASSWORD_VAR, StratumClient, start_stratum_server};
\ No newline at end of file
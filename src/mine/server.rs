@@ -1,11 +1,13 @@
+const CHANNEL_SIZE: usize = 16;
+
 use std::{
-    sync::{Arc, atomic::{AtomicBool, Ordering}}, 
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
     thread::{self, JoinHandle}
 };
 
 use anyhow::Result;
 
-use log::{info};
+use log::{info, warn};
 
 use tokio::{
     sync::{RwLock, mpsc},
@@ -13,35 +15,61 @@ use tokio::{
 
 use crate::{
     node::{MineCommand, NetworkCommand, Node},
-    block::Block,
+    block::{MiningJob, Solution},
 };
 
 fn spawn_threads(
-    block: Block,
+    job: Arc<MiningJob>,
     stop: Arc<AtomicBool>,
-    network_tx: mpsc::Sender<NetworkCommand>
+    solution_tx: mpsc::Sender<Solution>
 ) -> Vec<JoinHandle<()>>{
     let num_threads= thread::available_parallelism()
         .map(|n| n.get())
         .unwrap_or(1);
 
-    info!("Spawning: {} mining threads for block: {}", num_threads, block.get_height());
+    info!("Spawning: {} mining threads for block: {}", num_threads, job.get_height());
 
     let mut handles = Vec::new();
 
     for i in  0..num_threads{
-        let mut block_clone = block.clone();
+        let job = Arc::clone(&job);
         let stop_clone = Arc::clone(&stop);
-        let network_tx_clone = network_tx.clone();
-        let handle = thread::spawn(move ||{ 
-            block_clone.mine(stop_clone, i, network_tx_clone)
+        let solution_tx = solution_tx.clone();
+        let handle = thread::spawn(move ||{
+            if let Some(solution) = job.mine(&stop_clone, i)
+                && let Err(e) = solution_tx.blocking_send(solution){
+                warn!("Unable to hand in solution: {}", e);
+            }
         });
         handles.push(handle);
-    }   
+    }
 
     handles
 }
 
+//solutions for a job that no longer builds on the tip are dropped, returns whether it was passed on
+pub async fn submit_solution(
+    node: &Arc<RwLock<Node>>,
+    network_tx: &mpsc::Sender<NetworkCommand>,
+    solution: Solution
+) -> bool{
+    let stale = {
+        let node_read = node.read().await;
+        solution.is_stale(&node_read.get_prev_hash(), node_read.get_next_height())
+    };
+    if stale{
+        info!("Discarding stale solution for job {}", solution.job_id);
+        return false
+    }
+
+    info!("Submitting solution for job {}", solution.job_id);
+    if let Err(e) = network_tx.send(NetworkCommand::Block(solution.block)).await{
+        warn!("Unable to communicate on network channel: {}", e);
+        return false
+    }
+    true
+}
+
 pub async fn start_mining_server(
     node: Arc<RwLock<Node>>,
     mut miner_rx: mpsc::Receiver<MineCommand>,
@@ -49,41 +77,47 @@ pub async fn start_mining_server(
 ) -> Result<()>{
     info!("Started Mining Server");
 
+    let (solution_tx, mut solution_rx) = mpsc::channel::<Solution>(CHANNEL_SIZE);
     let mut stop = Arc::new(AtomicBool::new(false));
 
-    let block = node.write().await.get_next_block().await;
+    let job = Arc::new(node.write().await.new_mining_job().await);
 
     let mut handles = spawn_threads(
-        block, 
-        Arc::clone(&stop), 
-        network_tx.clone()
+        job,
+        Arc::clone(&stop),
+        solution_tx.clone()
     );
 
-    
-    while let Some(command) = miner_rx.recv().await{
-        match command{
-            MineCommand::Stop => {
-                info!("Shutting down mining threads");
-                stop.store(true, Ordering::Relaxed);
-                break;
-            }
-            MineCommand::UpdateBlock => {
-                stop.store(true, Ordering::Relaxed);
-                
-                for handle in handles{
-                    handle.join().expect("Error joining handles");
+    loop{
+        //a passed on solution comes back as UpdateBlock once the network handler has dealt with it
+        let restart = tokio::select! {
+            command = miner_rx.recv() => match command{
+                Some(MineCommand::UpdateBlock) => true,
+                Some(MineCommand::Stop) | None => {
+                    info!("Shutting down mining threads");
+                    stop.store(true, Ordering::Relaxed);
+                    break;
                 }
+            },
+            Some(solution) = solution_rx.recv() => !submit_solution(&node, &network_tx, solution).await,
+        };
+
+        if restart{
+            stop.store(true, Ordering::Relaxed);
 
-                stop = Arc::new(AtomicBool::new(false));
-                let block = node.write().await.get_next_block().await;
-                handles = spawn_threads(
-                    block, 
-                    Arc::clone(&stop), 
-                    network_tx.clone()
-                );
+            for handle in handles{
+                handle.join().expect("Error joining handles");
             }
+
+            stop = Arc::new(AtomicBool::new(false));
+            let job = Arc::new(node.write().await.new_mining_job().await);
+            handles = spawn_threads(
+                job,
+                Arc::clone(&stop),
+                solution_tx.clone()
+            );
         }
     };
 
     Ok(())
-}
\ No newline at end of file
+}
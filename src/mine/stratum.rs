@@ -0,0 +1,575 @@
+//leading zero bytes a share needs, capped at the block difficulty
+const SHARE_DIFFICULTY: usize = 1;
+
+//seconds before a job is rebuilt to pick up new mempool transactions
+const JOB_REFRESH_INTERVAL: usize = 30;
+
+const TIP_POLL_INTERVAL: u64 = 1;
+
+const EXTRANONCE_SIZE: usize = 4;
+
+const NONCE_SIZE: usize = 32;
+
+//jobs refreshed on the same tip stay valid, so shares in flight during a refresh still count
+const MAX_RECENT_JOBS: usize = 4;
+
+//worker requests are small, longer lines drop the connection
+const MAX_REQUEST_LENGTH: usize = 1_024;
+
+//a job takes at most this many shares from one worker, the next refresh brings a fresh job
+const MAX_SHARES_PER_JOB: usize = 10_000;
+
+pub const STRATUM_PASSWORD_VAR: &str = "COMP_COIN_STRATUM_PASSWORD";
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, atomic::{AtomicU32, Ordering}},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow, bail};
+
+use log::{info, warn};
+
+use serde::{Serialize, Deserialize};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{TcpListener, TcpStream, tcp::{OwnedReadHalf, OwnedWriteHalf}},
+    sync::{RwLock, mpsc, watch},
+    time,
+};
+
+use super::server::submit_solution;
+
+use crate::{
+    block::{Block, MiningJob, Solution},
+    node::{NetworkCommand, Node},
+    utils::{generate_nonce, get_timestamp},
+};
+
+//requests and responses are json objects, one per line
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StratumRequest{
+    pub id: u64,
+    #[serde(flatten)]
+    pub call: StratumCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum StratumCall{
+    Subscribe{
+        agent: String,
+    },
+    Authorize{
+        worker: String,
+        password: String,
+    },
+    Submit{
+        job_id: u64,
+        nonce: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StratumResponse{
+    pub id: u64,
+    pub result: serde_json::Value,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum StratumNotification{
+    Notify(JobNotice),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StratumMessage{
+    Response(StratumResponse),
+    Notification(StratumNotification),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscribeResult{
+    //every submitted nonce must start with these bytes so workers never repeat each other's work
+    pub extranonce: String,
+    pub share_difficulty: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareResult{
+    //the share also met the block difficulty and was passed on to the node
+    pub block: bool,
+}
+
+//the candidate block is sent whole since its hash covers every transaction, workers only change the nonce
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JobNotice{
+    pub job_id: u64,
+    pub height: usize,
+    pub prev_hash: String,
+    pub difficulty: usize,
+    pub share_difficulty: usize,
+    pub block: String,
+}
+
+impl JobNotice{
+    fn new(job: &MiningJob, share_difficulty: usize) -> Self{
+        Self {
+            job_id: job.id,
+            height: job.get_height(),
+            prev_hash: hex::encode(job.get_prev_hash()),
+            difficulty: job.difficulty,
+            share_difficulty: share_difficulty.min(job.difficulty),
+            block: job.template.to_hex(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ShareError{
+    NotSubscribed,
+    Unauthorized,
+    StaleJob(u64),
+    InvalidNonce(String),
+    Duplicate,
+    TooManyShares(u64),
+    LowDifficulty,
+}
+
+impl fmt::Display for ShareError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            ShareError::NotSubscribed => write!(f, "Worker has not subscribed"),
+            ShareError::Unauthorized => write!(f, "Worker is not authorized"),
+            ShareError::StaleJob(id) => write!(f, "Job {} is not a current job", id),
+            ShareError::InvalidNonce(e) => write!(f, "Nonce is not valid: {}", e),
+            ShareError::Duplicate => write!(f, "Share was already submitted"),
+            ShareError::TooManyShares(id) => write!(f, "Job {} has taken its share limit, wait for the next job", id),
+            ShareError::LowDifficulty => write!(f, "Share does not meet the share difficulty"),
+        }
+    }
+}
+
+impl std::error::Error for ShareError{}
+
+struct Session{
+    peer: SocketAddr,
+    extranonce: Vec<u8>,
+    subscribed: bool,
+    worker: Option<String>,
+    //newest last, all on the same tip
+    jobs: VecDeque<Arc<MiningJob>>,
+    //nonces by job id, only for the recent jobs
+    submitted: HashMap<u64, HashSet<Vec<u8>>>,
+    shares: usize,
+}
+
+impl Session{
+    fn is_ready(&self) -> bool{
+        self.subscribed && self.worker.is_some()
+    }
+
+    fn set_job(&mut self, job: Arc<MiningJob>){
+        if self.jobs.back().is_some_and(|last| last.get_prev_hash() != job.get_prev_hash()){
+            self.jobs.clear();
+        }
+        self.jobs.push_back(job);
+        if self.jobs.len() > MAX_RECENT_JOBS{
+            self.jobs.pop_front();
+        }
+        let jobs = &self.jobs;
+        self.submitted.retain(|job_id, _nonces| jobs.iter().any(|job| job.id == *job_id));
+    }
+
+    //the solution is only worth submitting when the flag says it meets the block difficulty
+    fn check_share(&mut self, job_id: u64, nonce: &str, share_difficulty: usize) -> Result<(Solution, bool), ShareError>{
+        if !self.subscribed{
+            return Err(ShareError::NotSubscribed)
+        }
+        if self.worker.is_none(){
+            return Err(ShareError::Unauthorized)
+        }
+        let Some(job) = self.jobs.iter().find(|job| job.id == job_id) else{
+            return Err(ShareError::StaleJob(job_id))
+        };
+
+        let nonce = hex::decode(nonce).map_err(|e| ShareError::InvalidNonce(e.to_string()))?;
+        if nonce.len() != NONCE_SIZE{
+            return Err(ShareError::InvalidNonce(format!("expected {} bytes", NONCE_SIZE)))
+        }
+        if !nonce.starts_with(&self.extranonce){
+            return Err(ShareError::InvalidNonce("missing extranonce".to_string()))
+        }
+        let submitted = self.submitted.entry(job_id).or_default();
+        if submitted.contains(&nonce){
+            return Err(ShareError::Duplicate)
+        }
+        if submitted.len() >= MAX_SHARES_PER_JOB{
+            return Err(ShareError::TooManyShares(job_id))
+        }
+
+        let mut block = job.template.clone();
+        block.set_nonce(nonce.clone());
+        if !block.meets_difficulty(share_difficulty.min(job.difficulty)){
+            return Err(ShareError::LowDifficulty)
+        }
+        submitted.insert(nonce);
+        self.shares += 1;
+        let is_block = block.meets_difficulty(job.difficulty);
+        Ok((Solution { job_id, block }, is_block))
+    }
+}
+
+async fn send<T: Serialize>(writer: &mut OwnedWriteHalf, message: &T) -> Result<()>{
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+//partial reads stay in buf, so this is safe to cancel in a select and call again
+async fn read_request(reader: &mut BufReader<OwnedReadHalf>, buf: &mut Vec<u8>) -> Result<Option<String>>{
+    let limit = (MAX_REQUEST_LENGTH + 1).saturating_sub(buf.len());
+    (&mut *reader).take(limit as u64).read_until(b'\n', buf).await?;
+    if buf.last() != Some(&b'\n'){
+        if buf.len() > MAX_REQUEST_LENGTH{
+            bail!("Stratum request longer than {} bytes", MAX_REQUEST_LENGTH)
+        }
+        if buf.is_empty(){
+            return Ok(None)
+        }
+    }
+    let line = String::from_utf8(std::mem::take(buf))?;
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+async fn notify(writer: &mut OwnedWriteHalf, session: &mut Session, job: Arc<MiningJob>, share_difficulty: usize) -> Result<()>{
+    let notice = JobNotice::new(&job, share_difficulty);
+    session.set_job(job);
+    send(writer, &StratumNotification::Notify(notice)).await
+}
+
+async fn handle_worker(
+    stream: TcpStream,
+    peer: SocketAddr,
+    extranonce: Vec<u8>,
+    mut jobs: watch::Receiver<Option<Arc<MiningJob>>>,
+    solutions: mpsc::Sender<Solution>,
+    share_difficulty: usize,
+    password: Option<Arc<String>>,
+) -> Result<()>{
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    let mut session = Session {
+        peer,
+        extranonce,
+        subscribed: false,
+        worker: None,
+        jobs: VecDeque::new(),
+        submitted: HashMap::new(),
+        shares: 0,
+    };
+
+    loop{
+        tokio::select! {
+            line = read_request(&mut reader, &mut buf) => {
+                let Some(line) = line? else{
+                    break
+                };
+                let request: StratumRequest = match serde_json::from_str(&line){
+                    Ok(request) => request,
+                    Err(e) => {
+                        warn!("Invalid stratum request from {}: {}", peer, e);
+                        continue
+                    }
+                };
+
+                let was_ready = session.is_ready();
+                let (result, error) = match request.call{
+                    StratumCall::Subscribe { agent } => {
+                        info!("Stratum worker {} subscribed: {}", peer, agent);
+                        session.subscribed = true;
+                        let result = SubscribeResult {
+                            extranonce: hex::encode(&session.extranonce),
+                            share_difficulty
+                        };
+                        (serde_json::to_value(result)?, None)
+                    }
+                    StratumCall::Authorize { worker, password: given } => {
+                        if worker.is_empty(){
+                            (serde_json::Value::Bool(false), Some("Worker name is empty".to_string()))
+                        }else if password.as_ref().is_some_and(|password| **password != given){
+                            warn!("Stratum worker {} gave the wrong password", peer);
+                            (serde_json::Value::Bool(false), Some("Wrong password".to_string()))
+                        }else{
+                            info!("Stratum worker {} authorized as {}", peer, worker);
+                            session.worker = Some(worker);
+                            (serde_json::Value::Bool(true), None)
+                        }
+                    }
+                    StratumCall::Submit { job_id, nonce } => {
+                        match session.check_share(job_id, &nonce, share_difficulty){
+                            Ok((solution, is_block)) => {
+                                if is_block{
+                                    info!("Stratum worker {} found block {}", peer, solution.block.get_height());
+                                    solutions.send(solution).await?;
+                                }
+                                (serde_json::to_value(ShareResult { block: is_block })?, None)
+                            }
+                            Err(e) => (serde_json::Value::Null, Some(e.to_string())),
+                        }
+                    }
+                };
+                send(&mut writer, &StratumResponse { id: request.id, result, error }).await?;
+
+                if !was_ready && session.is_ready(){
+                    let job = jobs.borrow_and_update().clone();
+                    if let Some(job) = job{
+                        notify(&mut writer, &mut session, job, share_difficulty).await?;
+                    }
+                }
+            }
+            changed = jobs.changed() => {
+                if changed.is_err(){
+                    break
+                }
+                let job = jobs.borrow_and_update().clone();
+                if let Some(job) = job && session.is_ready(){
+                    notify(&mut writer, &mut session, job, share_difficulty).await?;
+                }
+            }
+        }
+    }
+
+    info!("Stratum worker {} disconnected after {} shares", session.peer, session.shares);
+    Ok(())
+}
+
+//accepts workers until the listener fails, each connection gets its own extranonce
+//with a password set, workers must give it to be authorized
+pub async fn serve_stratum(
+    listener: TcpListener,
+    jobs: watch::Receiver<Option<Arc<MiningJob>>>,
+    solutions: mpsc::Sender<Solution>,
+    share_difficulty: usize,
+    password: Option<String>,
+) -> Result<()>{
+    let password = password.map(Arc::new);
+    let next_extranonce = AtomicU32::new(0);
+    loop{
+        let (stream, peer) = listener.accept().await?;
+        let extranonce = next_extranonce.fetch_add(1, Ordering::Relaxed).to_be_bytes()[..EXTRANONCE_SIZE].to_vec();
+        let jobs = jobs.clone();
+        let solutions = solutions.clone();
+        let password = password.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_worker(stream, peer, extranonce, jobs, solutions, share_difficulty, password).await{
+                warn!("Stratum worker {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+//new jobs follow the tip, and are rebuilt now and then so new transactions get mined
+async fn update_jobs(node: Arc<RwLock<Node>>, jobs: watch::Sender<Option<Arc<MiningJob>>>){
+    loop{
+        let stale = {
+            let node_read = node.read().await;
+            jobs.borrow().as_ref().is_none_or(|job| {
+                job.is_stale(&node_read.get_prev_hash(), node_read.get_next_height())
+                || job.get_age(get_timestamp()) >= JOB_REFRESH_INTERVAL
+            })
+        };
+        if stale{
+            let job = node.write().await.new_mining_job().await;
+            jobs.send_replace(Some(Arc::new(job)));
+        }
+        time::sleep(Duration::from_secs(TIP_POLL_INTERVAL)).await;
+    }
+}
+
+//local workers only unless public is set, which also requires a password
+pub async fn start_stratum_server(
+    node: Arc<RwLock<Node>>,
+    network_tx: mpsc::Sender<NetworkCommand>,
+    port: usize,
+    public: bool,
+    password: Option<String>,
+) -> Result<()>{
+    if public && password.is_none(){
+        return Err(anyhow!("A public stratum server needs a password in {}", STRATUM_PASSWORD_VAR))
+    }
+    let host = if public {"0.0.0.0"} else {"127.0.0.1"};
+    let socket_addr = format!("{}:{}", host, port);
+    let listener = TcpListener::bind(&socket_addr).await?;
+    info!("Stratum server listening on {}", socket_addr);
+
+    let (jobs_tx, jobs_rx) = watch::channel(None);
+    let (solutions_tx, mut solutions_rx) = mpsc::channel::<Solution>(16);
+
+    tokio::spawn(update_jobs(Arc::clone(&node), jobs_tx));
+
+    //the network handler checks the block again before adding and relaying it
+    tokio::spawn(async move {
+        while let Some(solution) = solutions_rx.recv().await{
+            submit_solution(&node, &network_tx, solution).await;
+        }
+    });
+
+    serve_stratum(listener, jobs_rx, solutions_tx, SHARE_DIFFICULTY, password).await
+}
+
+//minimal worker used to drive the server, e.g. over loopback in tests
+pub struct StratumClient{
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+    notices: Vec<JobNotice>,
+}
+
+impl StratumClient{
+    pub async fn connect(addr: SocketAddr) -> Result<Self>{
+        let (reader, writer) = TcpStream::connect(addr).await?.into_split();
+        Ok(Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+            next_id: 0,
+            notices: Vec::new()
+        })
+    }
+
+    async fn read_message(&mut self) -> Result<StratumMessage>{
+        let line = self.lines.next_line().await?.ok_or(anyhow!("Stratum server closed the connection"))?;
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    //job notices that arrive while waiting are kept for next_job
+    async fn call(&mut self, call: StratumCall) -> Result<serde_json::Value>{
+        let id = self.next_id;
+        self.next_id += 1;
+        send(&mut self.writer, &StratumRequest { id, call }).await?;
+
+        loop{
+            match self.read_message().await?{
+                StratumMessage::Response(response) if response.id == id => {
+                    return match response.error{
+                        Some(e) => Err(anyhow!(e)),
+                        None => Ok(response.result),
+                    }
+                }
+                StratumMessage::Response(response) => warn!("Unexpected stratum response: {}", response.id),
+                StratumMessage::Notification(StratumNotification::Notify(notice)) => self.notices.push(notice),
+            }
+        }
+    }
+
+    pub async fn subscribe(&mut self, agent: &str) -> Result<SubscribeResult>{
+        Ok(serde_json::from_value(self.call(StratumCall::Subscribe { agent: agent.to_string() }).await?)?)
+    }
+
+    pub async fn authorize(&mut self, worker: &str, password: &str) -> Result<()>{
+        self.call(StratumCall::Authorize { worker: worker.to_string(), password: password.to_string() }).await?;
+        Ok(())
+    }
+
+    pub async fn submit(&mut self, job_id: u64, nonce: &[u8]) -> Result<ShareResult>{
+        Ok(serde_json::from_value(self.call(StratumCall::Submit { job_id, nonce: hex::encode(nonce) }).await?)?)
+    }
+
+    //the latest job, waiting for one if none has arrived
+    pub async fn next_job(&mut self) -> Result<JobNotice>{
+        if let Some(notice) = self.notices.pop(){
+            self.notices.clear();
+            return Ok(notice)
+        }
+        loop{
+            if let StratumMessage::Notification(StratumNotification::Notify(notice)) = self.read_message().await?{
+                return Ok(notice)
+            }
+        }
+    }
+
+    //searches for a nonce meeting the difficulty, keeping the extranonce prefix
+    pub fn find_nonce(notice: &JobNotice, extranonce: &[u8], difficulty: usize) -> Result<Vec<u8>>{
+        let mut block = Block::from_hex(&notice.block)?;
+        loop{
+            let mut nonce = extranonce.to_vec();
+            nonce.extend(&generate_nonce()[extranonce.len()..]);
+            block.set_nonce(nonce.clone());
+            if block.meets_difficulty(difficulty){
+                return Ok(nonce)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[tokio::test]
+    async fn loopback_worker_submits_shares_and_blocks(){
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let block = Block::new(0, 1, 0, Vec::new(), vec![0; 32]);
+        let (jobs_tx, jobs_rx) = watch::channel(Some(Arc::new(MiningJob::new(7, block))));
+        let (solutions_tx, mut solutions_rx) = mpsc::channel(4);
+        tokio::spawn(serve_stratum(listener, jobs_rx, solutions_tx, 0, Some("secret".to_string())));
+
+        let mut client = StratumClient::connect(addr).await.unwrap();
+        assert!(client.submit(7, &[0; NONCE_SIZE]).await.is_err());
+
+        let subscription = client.subscribe("test").await.unwrap();
+        let extranonce = hex::decode(&subscription.extranonce).unwrap();
+        assert!(client.authorize("worker", "wrong").await.is_err());
+        client.authorize("worker", "secret").await.unwrap();
+        let job = client.next_job().await.unwrap();
+        assert_eq!(job.job_id, 7);
+        assert_eq!(job.share_difficulty, 0);
+
+        assert!(client.submit(7, &[0xff; NONCE_SIZE]).await.is_err());
+        let nonce = StratumClient::find_nonce(&job, &extranonce, 1).unwrap();
+        assert!(client.submit(6, &nonce).await.is_err());
+        assert!(client.submit(7, &nonce).await.unwrap().block);
+        assert!(client.submit(7, &nonce).await.is_err());
+
+        let Solution{job_id, block: solution} = solutions_rx.recv().await.unwrap();
+        assert_eq!(job_id, 7);
+        assert_eq!(solution.check_header(), Ok(()));
+
+        let next = Block::new(1, 1, 0, Vec::new(), solution.calculate_hash());
+        jobs_tx.send_replace(Some(Arc::new(MiningJob::new(8, next.clone()))));
+        let job = client.next_job().await.unwrap();
+        assert_eq!(job.job_id, 8);
+
+        //a refresh on the same tip keeps the previous job valid, a new tip does not
+        jobs_tx.send_replace(Some(Arc::new(MiningJob::new(9, next))));
+        assert_eq!(client.next_job().await.unwrap().job_id, 9);
+        let nonce = StratumClient::find_nonce(&job, &extranonce, 1).unwrap();
+        assert!(client.submit(8, &nonce).await.unwrap().block);
+        assert!(client.submit(7, &nonce).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn long_requests_drop_the_connection(){
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (_jobs_tx, jobs_rx) = watch::channel(None);
+        let (solutions_tx, _solutions_rx) = mpsc::channel(4);
+        tokio::spawn(serve_stratum(listener, jobs_rx, solutions_tx, 0, None));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&[b'a'; MAX_REQUEST_LENGTH * 2]).await.unwrap();
+        let mut response = Vec::new();
+        let read = time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await.unwrap();
+        assert!(read.is_err() || response.is_empty());
+    }
+}
@@ -138,6 +138,7 @@ pub async fn command_handling(
                     }
                 } else{
                     warn!("Is old Transaction");
+                    node.write().await.release_transaction(&transaction);
                 }
 
             }
@@ -22,6 +22,7 @@ pub enum NetMessage{
     Peers(Vec<SocketAddr>),
     Ping,
     Pong,
+    GetTransactions(Vec<Vec<u8>>),
 }
 
 impl NetMessage{
@@ -7,7 +7,7 @@ use super::{
     protocol::NetMessage,
 };
 
-use crate::{node::{MineCommand, NetworkCommand, Node}};
+use crate::{node::{MineCommand, NetworkCommand, Node, Processed}};
 
 use anyhow::Result;
 
@@ -146,23 +146,57 @@ pub async fn protocal_handling(
                     }
 
                     Ok(NetMessage::Transaction(transaction)) => {
-                        if !node.read().await.is_new_transaction(&transaction).await{continue;}
-                        {
+                        let processed = {
                             let mut node_write = node.write().await;
-                            node_write.add_transaction(transaction.clone()).await;
+                            node_write.process_transaction(transaction, peer).await
+                        };
+
+                        match processed{
+                            Processed::Accepted(transactions) => {
+                                let peer_manager_read = peer_manager.read().await;
+                                for transaction in transactions{
+                                    let response = ConnectionResponse::message(
+                                        NetMessage::Transaction(
+                                            transaction
+                                        ).to_bytes()
+                                    );
+                                    peer_manager_read.broadcast(response).await;
+                                }
+                            }
+                            Processed::Orphan(missing) => {
+                                info!("Requesting {} missing parents from: {}", missing.len(), &peer);
+                                let response = ConnectionResponse::message(
+                                    NetMessage::GetTransactions(missing).to_bytes()
+                                );
+                                let peer_manager_read = peer_manager.read().await;
+                                if let Err(e) = peer_manager_read.send(&peer, response).await{
+                                    warn!("Error sending message to: {}: {}", &peer, e);
+                                }
+                            }
+                            Processed::Rejected => {}
                         }
-                        
-                        {
+                    }
+
+                    Ok(NetMessage::GetTransactions(txids)) => {
+                        let transactions: Vec<_> = {
+                            let node_read = node.read().await;
+                            txids.iter()
+                                .filter_map(|txid| node_read.mempool.get(txid))
+                                .map(|entry| entry.transaction)
+                                .collect()
+                        };
+
+                        let peer_manager_read = peer_manager.read().await;
+                        for transaction in transactions{
                             let response = ConnectionResponse::message(
                                 NetMessage::Transaction(
-                                    transaction.clone()
+                                    transaction
                                 ).to_bytes()
                             );
-                            let peer_manager_read = peer_manager.read().await;
-                            peer_manager_read.broadcast(response).await;
+                            if let Err(e) = peer_manager_read.send(&peer, response).await{
+                                warn!("Error sending message to: {}: {}", &peer, e);
+                            }
                         }
-
-
                     }
                     Ok(NetMessage::GetInv) => {
                         let response = ConnectionResponse::message(
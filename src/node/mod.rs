@@ -2,13 +2,25 @@ mod node;
 mod commands;
 mod server;
 mod parser;
+mod rescan;
+mod wallets;
 
 pub use server::{
     start_server
 };
 
 pub use node::{
-    Node
+    Node,
+    Processed
+};
+pub use rescan::{
+    rescan_wallet,
+    RescanStatus
+};
+pub use wallets::{
+    create_wallet,
+    import_key,
+    unlock_wallet
 };
 pub use commands::{
     MineCommand, 
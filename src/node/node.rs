@@ -2,23 +2,32 @@ const DIFFICULTY: usize = 2;
 
 const FILE_PATH: &str = "configs/node.json";
 
+const MEMPOOL_PATH: &str = "configs/mempool.json";
+
 #[allow(unused_imports)]
 use log::info;
 
-use anyhow::{Result};
+use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 
 use std::{
-    net::{IpAddr}, 
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr}, 
     sync::Arc,
-    fs::File,
+    fs::{self, File},
+    io::Write,
 };
 
 use tokio::sync::RwLock;
 
+use super::rescan::RescanProgress;
+
 use crate::{
-    block::{Block, Mempool, Transaction, UTXOS, Wallet}, 
-    ui::{NodeStatus, UserStatus}, utils::{get_global_ip, get_local_ip}
+    block::{
+        Admission, Block, BlockError, BlockTemplate, DEFAULT_WALLET, Fee, FeeEstimate, FeeEstimator, MAX_TARGET, Mempool, MempoolEntry, MempoolError, MempoolLimits, MiningJob, OrphanPool, Psbt, Transaction, 
+        TransactionWithFee, UTXOS, Wallet, fee_rate
+    }, 
+    ui::{NodeStatus, UserStatus}, utils::{get_global_ip, get_local_ip, get_timestamp}
 };
 pub struct Node{
     pub height: Option<usize>,
@@ -26,32 +35,95 @@ pub struct Node{
     pub block_chain: Vec<Block>,
     pub config: Config,
     pub utxos: Arc<RwLock<UTXOS>>,
-    pub wallet: Wallet,
+    pub wallets: HashMap<String, Wallet>,
+    pub mining_wallet: String,
+    pub rescan_progress: Arc<RescanProgress>,
+    pub fee_estimator: FeeEstimator,
+    pub orphans: OrphanPool,
+    //txids of confirmed transactions, tells a spent output apart from one not seen yet
+    confirmed: HashSet<Vec<u8>>,
+    next_job_id: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestAccept{
+    pub txid: String,
+    pub allowed: bool,
+    pub size: usize,
+    pub fee: Option<usize>,
+    pub fee_rate: Option<usize>,
+    //mempool transactions it would replace
+    pub replaces: Vec<String>,
+    pub reason: Option<String>,
+}
+
+//what became of a transaction relayed by a peer
+pub enum Processed{
+    //the transaction and any orphans it unlocked, in the order they were accepted
+    Accepted(Vec<Transaction>),
+    //held until the listed parents arrive
+    Orphan(Vec<Vec<u8>>),
+    Rejected,
 }
 
 impl Node{
-    pub async fn new() -> Self{
+    pub async fn new(wallet: Wallet) -> Self{
         Self {
             height: None, 
             mempool: Mempool::new(), 
             block_chain: Vec::new(), 
             config:Config::tmp_new().await, 
             utxos: Arc::new(RwLock::new(UTXOS::new())), 
-            wallet: Wallet::new() 
+            mining_wallet: wallet.get_name().to_string(),
+            wallets: HashMap::from([(wallet.get_name().to_string(), wallet)]),
+            rescan_progress: Arc::new(RescanProgress::default()),
+            fee_estimator: FeeEstimator::default(),
+            orphans: OrphanPool::default(),
+            confirmed: HashSet::new(),
+            next_job_id: 0,
         }
     }
 
     pub async fn load() -> Result<Self>{
         let file = File::open(FILE_PATH)?;
         let node_data: NodeSerde = serde_json::from_reader(file)?;
-        Ok(Self { 
+        let confirmed = node_data.block_chain.iter()
+            .flat_map(|block| block.get_transactions().into_iter().map(|transaction| transaction.get_hash()))
+            .collect();
+        let mut node = Self { 
             height: node_data.height, 
-            mempool: node_data.mempool, 
+            mempool: Mempool::new(), 
             block_chain: node_data.block_chain, 
             config: node_data.config, 
             utxos: Arc::new(RwLock::new(node_data.utxos)), 
-            wallet: node_data.wallet 
-        })
+            wallets: HashMap::new(),
+            mining_wallet: node_data.mining_wallet.unwrap_or(DEFAULT_WALLET.to_string()),
+            rescan_progress: Arc::new(RescanProgress::default()),
+            fee_estimator: node_data.fee_estimator,
+            orphans: OrphanPool::default(),
+            confirmed,
+            next_job_id: 0,
+        };
+
+        //older nodes saved the mempool inside the node file
+        let entries = match node_data.mempool{
+            Some(mempool) => mempool.get_entries(),
+            None => Self::read_mempool()?,
+        };
+        node.load_mempool(entries).await;
+
+        //older nodes kept their only wallet inside the node file
+        if let Some(mut wallet) = node_data.wallet{
+            wallet.sync_mempool(&node.mempool);
+            node.wallets.insert(wallet.get_name().to_string(), wallet);
+        }
+        for name in node_data.wallets{
+            node.load_wallet(&name)?;
+        }
+        if !node.wallets.contains_key(&node.mining_wallet){
+            return Err(anyhow!("Mining wallet is not loaded: {}", node.mining_wallet))
+        }
+        Ok(node)
     }
 
     pub async fn save(&self) -> Result<()>{
@@ -59,17 +131,81 @@ impl Node{
 
         let node_data = NodeSerde{
             height: self.height,
-            mempool: self.mempool.clone(),
+            mempool: None,
             block_chain: self.block_chain.clone(),
             config: self.config.clone(),
             utxos,
-            wallet: self.wallet.clone()
+            wallet: None,
+            wallets: self.wallets.keys().cloned().collect(),
+            mining_wallet: Some(self.mining_wallet.clone()),
+            fee_estimator: self.fee_estimator.clone(),
         };
+        for wallet in self.wallets.values(){
+            self.save_wallet(wallet)?;
+        }
         let file = File::create(FILE_PATH)?;
         serde_json::to_writer(file, &node_data)?;
+        self.save_mempool().await
+    }
+
+    pub async fn save_mempool(&self) -> Result<()>{
+        let dump = self.dump_mempool()?;
+        tokio::task::spawn_blocking(move || Self::write_mempool(&dump)).await?
+    }
+
+    //cheap enough to do under the node lock, the file write is left to write_mempool
+    pub fn dump_mempool(&self) -> Result<Vec<u8>>{
+        Ok(serde_json::to_vec(&self.mempool.get_entries())?)
+    }
+
+    //written and synced to a temporary file first so a crash mid-write leaves the previous dump intact
+    pub fn write_mempool(dump: &[u8]) -> Result<()>{
+        let tmp_path = format!("{}.tmp", MEMPOOL_PATH);
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(dump)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, MEMPOOL_PATH)?;
         Ok(())
     }
 
+    fn read_mempool() -> Result<Vec<MempoolEntry>>{
+        match File::open(MEMPOOL_PATH){
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    //every entry is checked again against the current chain, anything no longer valid is dropped
+    async fn load_mempool(&mut self, entries: Vec<MempoolEntry>){
+        let total = entries.len();
+        for entry in entries{
+            let txid = entry.transaction.get_hash();
+            if entry.fee_delta != 0 && self.mempool.get_fee_delta(&txid) == 0{
+                self.mempool.prioritise(txid, entry.fee_delta, get_timestamp());
+            }
+            if let Ok(Admission{fee, replaces}) = self.check_transaction(&entry.transaction).await 
+                && replaces.is_empty(){
+                self.mempool.add_transaction_at(entry.transaction, fee, entry.added);
+            }
+        }
+        let removed = self.mempool.enforce_limits(get_timestamp());
+        self.fee_estimator.untrack(&removed);
+        info!("Loaded {} of {} saved mempool transactions", self.mempool.size(), total);
+    }
+
+    //checked before the keystore is written, so restoring never replaces an existing wallet
+    pub fn restore_wallet(&mut self, name: &str, phrase: &str, passphrase: &str) -> Result<()>{
+        self.check_new_wallet(name)?;
+        let mut wallet = Wallet::from_mnemonic(name, phrase, passphrase)?;
+        for block in self.block_chain.iter(){
+            wallet.add_block(block);
+        }
+        wallet.sync_mempool(&self.mempool);
+        info!("Restored wallet {} with funds: {}", name, wallet.get_funds());
+        self.add_new_wallet(wallet)
+    }
+
     pub fn get_height(&self) -> Option<usize>{
         self.height
     }
@@ -95,12 +231,34 @@ impl Node{
     }
 
     pub async fn is_new_block(&self, block: &Block) -> bool{
-        self.utxos.read().await.validate_block(&block, self.get_reward())
-        && block.get_height() == self.get_next_height()
+        match self.check_block(block).await{
+            Ok(()) => true,
+            Err(e) => {
+                info!("Rejected block: {}", e);
+                false
+            }
+        }
+    }
 
+    //full validation of a block meant to extend the current tip
+    pub async fn check_block(&self, block: &Block) -> Result<(), BlockError>{
+        if block.get_height() != self.get_next_height(){
+            return Err(BlockError::WrongHeight { expected: self.get_next_height(), found: block.get_height() })
+        }
+        if block.get_prev_hash() != self.get_prev_hash(){
+            return Err(BlockError::WrongPrevHash)
+        }
+        if block.get_difficulty() != self.get_difficulty(){
+            return Err(BlockError::WrongDifficulty { expected: self.get_difficulty(), found: block.get_difficulty() })
+        }
+        block.check_header()?;
+        if !self.utxos.read().await.validate_block(block, self.get_reward()){
+            return Err(BlockError::InvalidTransactions)
+        }
+        Ok(())
     }
     
-    fn get_prev_hash(&self) -> Vec<u8>{
+    pub fn get_prev_hash(&self) -> Vec<u8>{
         if let Some(block) = self.block_chain.last(){
             block.calculate_hash()
         } else{
@@ -110,44 +268,285 @@ impl Node{
 
     pub async fn add_block(&mut self, block: &Block){
         self.block_chain.push(block.clone());
-        self.mempool.add_block(block);
-        self.wallet.add_block(block);
+        let conflicts = self.mempool.add_block(block);
+        if !conflicts.is_empty(){
+            info!("Block confirmed competing spends, evicted {} mempool transactions", conflicts.len());
+            self.fee_estimator.untrack(&conflicts);
+        }
+        let expired = self.mempool.expire(get_timestamp());
+        if !expired.is_empty(){
+            info!("Expired {} mempool transactions", expired.len());
+            self.fee_estimator.untrack(&expired);
+        }
+        self.fee_estimator.add_block(block);
+        for wallet in self.wallets.values_mut(){
+            wallet.add_block(block);
+            wallet.sync_mempool(&self.mempool);
+        }
         self.utxos.write().await.add_block(block);
         self.incr_height();
+
+        let parents: Vec<Vec<u8>> = block.get_transactions().iter().map(|transaction| transaction.get_hash()).collect();
+        self.confirmed.extend(parents.iter().cloned());
+
+        //orphans are waiting on confirmed outputs, which are keyed by the mined txids in the block
+        self.orphans.remove_block(block);
+        self.orphans.expire(get_timestamp());
+        let accepted = self.process_orphans(parents).await;
+        if !accepted.is_empty(){
+            info!("Block released {} orphan transactions", accepted.len());
+        }
+    }
+
+    //an input whose transaction confirmed but whose output is gone was spent, that is never worth holding as an orphan
+    pub async fn check_transaction(&self, transaction: &Transaction) -> Result<Admission, MempoolError>{
+        match self.mempool.check_transaction(transaction, &*self.utxos.read().await){
+            Err(MempoolError::MissingInputs) if self.get_missing_parents(transaction).await.iter()
+                .any(|parent| self.confirmed.contains(parent)) => Err(MempoolError::InputsSpent),
+            result => result,
+        }
+    }
+
+    //the checks a relayed transaction goes through, without adding or relaying it
+    pub async fn test_accept(&self, transaction: &Transaction) -> TestAccept{
+        let size = transaction.get_size();
+        let mut result = TestAccept {
+            txid: hex::encode(transaction.get_hash()),
+            allowed: false,
+            size,
+            fee: None,
+            fee_rate: None,
+            replaces: Vec::new(),
+            reason: None,
+        };
+        match self.check_transaction(transaction).await{
+            Ok(Admission{fee, replaces}) => {
+                result.allowed = true;
+                result.fee = Some(fee);
+                result.fee_rate = Some(fee_rate(fee, size));
+                result.replaces = replaces.iter().map(|tx| hex::encode(tx.get_hash())).collect();
+            }
+            Err(e) => result.reason = Some(e.to_string()),
+        }
+        result
+    }
+
+    pub fn prioritise(&mut self, txid: &str, fee_delta: isize) -> Result<()>{
+        let hash = hex::decode(txid)?;
+        self.mempool.prioritise(hash.clone(), fee_delta, get_timestamp());
+        info!("Fee delta for {} is now {}", txid, self.mempool.get_fee_delta(&hash));
+        Ok(())
     }
 
     pub async fn is_new_transaction(&self, transaction: &Transaction) -> bool{
-        if !self.utxos.read().await.validate_pending_transaction(transaction){return false}
+        match self.check_transaction(transaction).await{
+            Ok(_) => true,
+            Err(e) => {
+                info!("Rejected transaction: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn accept_transaction(&mut self, transaction: Transaction) -> Result<(), MempoolError>{
+        let Admission{fee, replaces} = match self.check_transaction(&transaction).await{
+            Ok(admission) => admission,
+            Err(e) => {
+                self.release_transaction(&transaction);
+                return Err(e)
+            }
+        };
+        let txid = transaction.get_hash();
+
+        if !replaces.is_empty(){
+            info!("Replacing {} mempool transactions", replaces.len());
+            self.mempool.remove(replaces.clone());
+            self.fee_estimator.untrack(&replaces);
+        }
+        let evicted = self.mempool.add_transaction(transaction.clone(), fee);
+        if !evicted.is_empty(){
+            info!("Mempool full, evicted {} transactions", evicted.len());
+            self.fee_estimator.untrack(&evicted);
+        }
+
+        if replaces.is_empty() && evicted.is_empty(){
+            for wallet in self.wallets.values_mut(){
+                wallet.add_pending_transaction(&transaction);
+            }
+        }else{
+            for wallet in self.wallets.values_mut(){
+                wallet.sync_mempool(&self.mempool);
+            }
+        }
 
-        !self.mempool.contains(transaction)
+        if evicted.iter().any(|tx| tx.get_hash() == txid){
+            return Err(MempoolError::MempoolFull)
+        }
+        self.fee_estimator.track(&transaction, fee, self.get_next_height());
+        Ok(())
+    }
+
+    //wallets reserve outputs when they build a transaction, hand them back if it never makes the mempool
+    pub fn release_transaction(&mut self, transaction: &Transaction){
+        for wallet in self.wallets.values_mut(){
+            if wallet.is_involved(transaction){
+                wallet.sync_mempool(&self.mempool);
+            }
+        }
     }
 
     pub async fn add_transaction(&mut self, transaction: Transaction){
-        let fee = self.utxos.read().await.calculate_fee(&transaction);
-        self.mempool.add_transaction(transaction, fee);
+        if let Err(e) = self.accept_transaction(transaction).await{
+            info!("Rejected transaction: {}", e);
+        }
+    }
+
+    //parents of the inputs missing from the confirmed utxo set
+    async fn get_missing_parents(&self, transaction: &Transaction) -> Vec<Vec<u8>>{
+        let utxos = self.utxos.read().await;
+        let mut missing: Vec<Vec<u8>> = Vec::new();
+        for input in transaction.inputs.iter(){
+            if utxos.get(&input.prev, input.output_index).is_none() && !missing.contains(&input.prev){
+                missing.push(input.prev.clone());
+            }
+        }
+        missing
+    }
+
+    //transactions that arrive before their parents are kept as orphans rather than dropped,
+    //spends of spent outputs fail with InputsSpent instead and are rejected straight away
+    pub async fn process_transaction(&mut self, transaction: Transaction, peer: SocketAddr) -> Processed{
+        let txid = transaction.get_hash();
+        match self.accept_transaction(transaction.clone()).await{
+            Ok(()) => {
+                let mut accepted = vec![transaction];
+                accepted.extend(self.process_orphans(vec![txid]).await);
+                Processed::Accepted(accepted)
+            }
+            Err(MempoolError::MissingInputs) => {
+                let missing = self.get_missing_parents(&transaction).await;
+                if self.orphans.add(transaction, missing.clone(), peer, get_timestamp()){
+                    info!("Holding orphan transaction {}, waiting on {} parents", hex::encode(&txid), missing.len());
+                    Processed::Orphan(missing)
+                }else{
+                    Processed::Rejected
+                }
+            }
+            Err(e) => {
+                info!("Rejected transaction: {}", e);
+                Processed::Rejected
+            }
+        }
+    }
+
+    //retries orphans of each newly available parent, children accepted in turn release their own.
+    //unconfirmed outputs cannot be spent, so a parent accepted into the mempool only gets its
+    //orphans rejected, they can only be accepted once a block confirms the outputs they spend
+    async fn process_orphans(&mut self, mut parents: Vec<Vec<u8>>) -> Vec<Transaction>{
+        let mut accepted = Vec::new();
+        while let Some(parent) = parents.pop(){
+            for orphan in self.orphans.take_children(&parent){
+                let txid = orphan.transaction.get_hash();
+                match self.accept_transaction(orphan.transaction.clone()).await{
+                    Ok(()) => {
+                        parents.push(txid);
+                        accepted.push(orphan.transaction);
+                    }
+                    Err(MempoolError::MissingInputs) => {
+                        let missing = self.get_missing_parents(&orphan.transaction).await;
+                        self.orphans.add(orphan.transaction, missing, orphan.peer, get_timestamp());
+                    }
+                    Err(e) => info!("Rejected orphan transaction: {}", e),
+                }
+            }
+        }
+        accepted
     }
 
     pub async fn update_mempool(&mut self, mempool: Mempool){
-        if self.utxos.read().await.validate_mempool(&mempool){
-            self.mempool.update(mempool);
+        for entry in mempool.to_vec(){
+            if !self.mempool.contains(&entry.transaction){
+                let _ = self.accept_transaction(entry.transaction).await;
+            }
         }
     }
 
     pub async fn get_next_block(&mut self) -> Block{
-        let transactions = self.mempool.get_next_transactions(
+        self.get_next_block_with_fees().await.0
+    }
+
+    //fees line up with the transactions after the coinbase
+    async fn get_next_block_with_fees(&mut self) -> (Block, Vec<usize>){
+        let mining_key = self.wallets.get_mut(&self.mining_wallet)
+            .expect("mining wallet is never unloaded")
+            .get_mining_key();
+        let (transactions, fees) = self.mempool.get_next_transactions(
             Arc::clone(&self.utxos), 
-            self.wallet.get_public_key(), 
+            mining_key, 
             self.get_reward(), 
             self.get_version()
         ).await;
         
-        Block::new(
+        let block = Block::new(
             self.get_next_height(), 
             self.get_difficulty(), 
             self.get_version(), 
             transactions, 
             self.get_prev_hash()
-        )
+        );
+        (block, fees)
+    }
+
+    //job ids are shared by the local miner and remote workers
+    pub async fn new_mining_job(&mut self) -> MiningJob{
+        let block = self.get_next_block().await;
+        let job = MiningJob::new(self.next_job_id, block);
+        self.next_job_id += 1;
+        job
+    }
+
+    //what get_next_block would mine, laid out for miners outside this process
+    pub async fn get_block_template(&mut self) -> BlockTemplate{
+        let (block, fees) = self.get_next_block_with_fees().await;
+        BlockTemplate::new(&block, self.get_reward(), fees)
+    }
+
+    fn get_pending(&self, txid: &str) -> Result<TransactionWithFee>{
+        let hash = hex::decode(txid)?;
+        self.mempool.get(&hash).ok_or(anyhow!("Transaction not in mempool: {}", txid))
+    }
+
+    pub fn bump_fee(&mut self, wallet: &str, txid: &str, fee: Fee) -> Result<Transaction>{
+        let TransactionWithFee{transaction, fee: original_fee, ..} = self.get_pending(txid)?;
+        let version = self.get_version();
+        Ok(self.wallet_mut(wallet)?.bump_fee(version, &transaction, original_fee, fee)?)
+    }
+
+    //uses the slowest fee estimate, consolidation is never urgent
+    pub fn consolidate(&mut self, wallet: &str, threshold: usize, max_inputs: usize, max_rate: Option<usize>) -> Result<Transaction>{
+        let rate = self.estimate_fee(MAX_TARGET).fee_rate;
+        if let Some(max_rate) = max_rate && rate > max_rate{
+            return Err(anyhow!("Fees are too high to consolidate: rate {} is above {}", rate, max_rate))
+        }
+        let version = self.get_version();
+        Ok(self.wallet_mut(wallet)?.consolidate(version, threshold, max_inputs, rate)?)
+    }
+
+    //only confirmed outputs can be spent
+    pub async fn update_psbt(&self, psbt: &mut Psbt) -> usize{
+        let utxos = self.utxos.read().await;
+        psbt.update(|hash, index| utxos.get(hash, index))
+    }
+
+    pub fn get_pending_transactions(&self, wallet: &str) -> Result<Vec<TransactionWithFee>>{
+        let wallet = self.wallet(wallet)?;
+        Ok(self.mempool.to_vec().into_iter()
+            .filter(|entry| wallet.is_involved(&entry.transaction))
+            .collect())
+    }
+
+    pub fn estimate_fee(&self, target: usize) -> FeeEstimate{
+        self.fee_estimator.estimate(target, &self.mempool)
     }
 
     pub fn get_next_height(&self) -> usize{
@@ -158,23 +557,39 @@ impl Node{
         }
     }
 
-    pub fn get_user_status(&self) -> UserStatus{
-        UserStatus::new(
-            self.wallet.get_funds(),
-            self.wallet.get_public_key().to_hex()
-        )
+    pub fn get_user_status(&self, wallet: &str) -> Result<UserStatus>{
+        let wallet = self.wallet(wallet)?;
+        Ok(UserStatus::new(
+            wallet.get_funds(),
+            wallet.get_pending_funds(),
+            wallet.get_available_funds(),
+            wallet.get_watch_only_funds(),
+            wallet.get_receive_key().to_address().to_string(),
+            wallet.is_locked()
+        ))
     }
 
     pub fn get_node_status(&self) -> NodeStatus{
         NodeStatus::new(
-            match self.get_height() {
-                Some(height) => height,
-                None => 888888
-             }, 
+            self.get_height().unwrap_or(888888), 
              self.mempool.size(), 
+             self.mempool.get_bytes(),
+             self.mempool.get_min_fee_rate(get_timestamp()),
              self.get_difficulty())
     }
 
+    pub fn set_mempool_limits(&mut self, limits: MempoolLimits){
+        self.mempool.set_limits(limits);
+        let removed = self.mempool.enforce_limits(get_timestamp());
+        if !removed.is_empty(){
+            info!("Removed {} mempool transactions outside the new limits", removed.len());
+            self.fee_estimator.untrack(&removed);
+            for wallet in self.wallets.values_mut(){
+                wallet.sync_mempool(&self.mempool);
+            }
+        }
+    }
+
     pub fn set_port(&mut self, port: usize){
         self.config.set_port(port);
     }
@@ -226,9 +641,17 @@ impl Config{
 #[derive(Serialize, Deserialize, Debug)]
 struct NodeSerde {
     height: Option<usize>,
-    mempool: Mempool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mempool: Option<Mempool>,
     block_chain: Vec<Block>,
     config: Config,
     utxos: UTXOS,
-    wallet: Wallet,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wallet: Option<Wallet>,
+    #[serde(default)]
+    wallets: Vec<String>,
+    #[serde(default)]
+    mining_wallet: Option<String>,
+    #[serde(default)]
+    fee_estimator: FeeEstimator,
 }
\ No newline at end of file
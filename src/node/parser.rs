@@ -1,4 +1,8 @@
-use clap::Parser;
+const DEFAULT_UNLOCK_TIMEOUT: u64 = 15 * 60;
+
+use clap::{Parser, Subcommand};
+
+use crate::block::{DEFAULT_MAX_MEMPOOL_SIZE, DEFAULT_MEMPOOL_EXPIRY, DEFAULT_WALLET};
 
 #[derive(Parser)]
 #[command(name = "node-manager")]
@@ -10,4 +14,154 @@ pub struct Cli {
 
     #[arg(long)]
     pub port: usize,
+
+    //prompts for the wallet passphrase, otherwise it is read from COMP_COIN_PASSPHRASE
+    #[arg(long)]
+    pub ask_passphrase: bool,
+
+    //seconds the wallet unlocked at startup stays unlocked, 0 keeps it unlocked
+    #[arg(long, default_value_t = DEFAULT_UNLOCK_TIMEOUT)]
+    pub unlock_timeout: u64,
+
+    //wallet used for unlocking, restoring and maintenance commands
+    #[arg(long, default_value = DEFAULT_WALLET)]
+    pub wallet: String,
+
+    //bytes of transactions kept before the lowest fee rates are evicted
+    #[arg(long, default_value_t = DEFAULT_MAX_MEMPOOL_SIZE)]
+    pub mempool_size: usize,
+
+    //seconds a transaction may wait in the mempool before it is dropped
+    #[arg(long, default_value_t = DEFAULT_MEMPOOL_EXPIRY)]
+    pub mempool_expiry: usize,
+
+    //serves work to remote miners over the stratum protocol when set
+    #[arg(long)]
+    pub stratum_port: Option<usize>,
+
+    //listen on every interface instead of loopback, workers must then give COMP_COIN_STRATUM_PASSWORD
+    #[arg(long)]
+    pub stratum_public: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+//maintenance commands run against the saved node state, which is saved again before exiting
+#[derive(Subcommand)]
+pub enum Command {
+    Rescan {
+        #[arg(long, default_value_t = 0)]
+        from: usize,
+    },
+    ImportKey {
+        #[arg(long)]
+        key: String,
+
+        #[arg(long)]
+        rescan: bool,
+
+        #[arg(long, default_value_t = 0)]
+        from: usize,
+    },
+    ExportKey {
+        #[arg(long)]
+        address: String,
+    },
+    Watch {
+        #[arg(long)]
+        address: String,
+
+        #[arg(long)]
+        rescan: bool,
+
+        #[arg(long, default_value_t = 0)]
+        from: usize,
+    },
+    SignMessage {
+        #[arg(long)]
+        address: String,
+
+        #[arg(long)]
+        message: String,
+    },
+    VerifyMessage {
+        #[arg(long)]
+        address: String,
+
+        #[arg(long)]
+        message: String,
+
+        #[arg(long)]
+        signature: String,
+    },
+    ListUtxos,
+    //frozen outputs are skipped by coin selection
+    Freeze {
+        #[arg(long)]
+        outpoint: String,
+    },
+    Unfreeze {
+        #[arg(long)]
+        outpoint: String,
+    },
+    //prints the payment request uri to hand to the payer
+    CreateInvoice {
+        #[arg(long)]
+        amount: Option<usize>,
+
+        #[arg(long)]
+        label: Option<String>,
+
+        #[arg(long)]
+        message: Option<String>,
+
+        //seconds until the invoice expires
+        #[arg(long)]
+        expiry: Option<usize>,
+    },
+    ListInvoices,
+    CreateWallet {
+        #[arg(long)]
+        name: String,
+    },
+    LoadWallet {
+        #[arg(long)]
+        name: String,
+    },
+    UnloadWallet {
+        #[arg(long)]
+        name: String,
+    },
+    ListWallets,
+    SetMiningWallet {
+        #[arg(long)]
+        name: String,
+    },
+    //for an offline machine, prints the partially signed transaction with this wallet's signatures added
+    SignPsbt {
+        #[arg(long)]
+        psbt: String,
+
+        //sign without asking after the outputs and fee are shown
+        #[arg(long)]
+        yes: bool,
+    },
+    //releases the outputs a partially signed transaction reserved when it will never be broadcast
+    AbandonPsbt {
+        #[arg(long)]
+        psbt: String,
+    },
+    //runs the mempool checks on a raw transaction without adding or relaying it
+    TestAccept {
+        #[arg(long)]
+        transaction: String,
+    },
+    Prioritise {
+        #[arg(long)]
+        txid: String,
+
+        #[arg(long, allow_negative_numbers = true)]
+        fee_delta: isize,
+    },
 }
\ No newline at end of file
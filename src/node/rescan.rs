@@ -0,0 +1,98 @@
+const PROGRESS_INTERVAL: usize = 100;
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use anyhow::{Result, anyhow};
+
+use log::info;
+
+use serde::Serialize;
+
+use tokio::sync::RwLock;
+
+use super::node::Node;
+
+#[derive(Debug, Default)]
+pub struct RescanProgress{
+    running: AtomicBool,
+    height: AtomicUsize,
+    target: AtomicUsize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RescanStatus{
+    pub running: bool,
+    pub height: usize,
+    pub target: usize,
+}
+
+impl RescanProgress{
+    pub fn get_status(&self) -> RescanStatus{
+        RescanStatus {
+            running: self.running.load(Ordering::SeqCst),
+            height: self.height.load(Ordering::SeqCst),
+            target: self.target.load(Ordering::SeqCst),
+        }
+    }
+}
+
+//clears the running flag however the rescan ends
+struct RunningGuard(Arc<RescanProgress>);
+
+impl Drop for RunningGuard{
+    fn drop(&mut self){
+        self.0.running.store(false, Ordering::SeqCst);
+    }
+}
+
+//replays the chain into a copy of the wallet so the node stays usable while rescanning
+pub async fn rescan_wallet(node: Arc<RwLock<Node>>, name: &str, from_height: usize) -> Result<()>{
+    let (mut wallet, blocks, progress) = {
+        let node_read = node.read().await;
+        (
+            node_read.wallet(name)?.clone(),
+            node_read.block_chain.clone(),
+            Arc::clone(&node_read.rescan_progress)
+        )
+    };
+
+    if progress.running.swap(true, Ordering::SeqCst){
+        return Err(anyhow!("A rescan is already running"))
+    }
+    let _running = RunningGuard(Arc::clone(&progress));
+    progress.height.store(from_height, Ordering::SeqCst);
+    progress.target.store(blocks.len(), Ordering::SeqCst);
+
+    info!("Rescanning wallet {} from height: {}", name, from_height);
+    wallet.start_rescan(from_height);
+
+    for (index, block) in blocks.iter().enumerate(){
+        wallet.rescan_block(block, from_height);
+        progress.height.store(index + 1, Ordering::SeqCst);
+
+        if index.is_multiple_of(PROGRESS_INTERVAL){
+            info!("Rescanned: {}/{} blocks", index + 1, blocks.len());
+            tokio::task::yield_now().await;
+        }
+    }
+
+    let mut node_write = node.write().await;
+
+    //catch up on blocks that arrived during the rescan, the chain may also have been replaced by a shorter one
+    for block in node_write.block_chain.get(blocks.len()..).unwrap_or_default(){
+        wallet.add_block(block);
+    }
+
+    //the wallet may have been unloaded while rescanning
+    let node_write = &mut *node_write;
+    node_write.wallets.get_mut(name)
+        .map(|loaded| {
+            loaded.finish_rescan(wallet);
+            loaded.sync_mempool(&node_write.mempool);
+            info!("Rescan finished, funds: {}", loaded.get_funds());
+        })
+        .ok_or(anyhow!("Wallet unloaded during rescan: {}", name))
+}
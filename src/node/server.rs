@@ -1,5 +1,12 @@
 const CHANNEL_SIZE: usize = 100;
 
+const RELOCK_INTERVAL: u64 = 5;
+
+const MEMPOOL_DUMP_INTERVAL: u64 = 5 * 60;
+
+const PASSPHRASE_VAR: &str = "COMP_COIN_PASSPHRASE";
+const MNEMONIC_VAR: &str = "COMP_COIN_MNEMONIC";
+
 use anyhow::{Result, anyhow};
 
 use clap::Parser;
@@ -13,22 +20,53 @@ use super::node::Node;
 use super::commands::{
     MineCommand, NetworkCommand,
 };
-use super::parser::Cli;
+use super::parser::{Cli, Command};
+use super::rescan::rescan_wallet;
+use super::wallets::{check_unused_name, create_wallet, export_key, import_key};
 
 use crate::{
+    block::{Address, MempoolLimits, Psbt, Transaction, Wallet, parse_outpoint, verify_message},
     network::start_network_server,
-    mine::start_mining_server,
+    mine::{STRATUM_PASSWORD_VAR, start_mining_server, start_stratum_server},
     ui::start_ui_server,
 };
 
-use std::{sync::{
+use std::{io::{self, Write}, sync::{
         Arc, atomic::{AtomicBool, Ordering}
     }, time::Duration};
 
 const BOOTSTRAP_PORT: usize = 8333;
 const BOOTSTRAP_ADDR: &str = "192.168.1.150";
 
-async fn bootstrap_node_main(node: Arc<RwLock<Node>>) -> Result<()>{
+//the worker password is read from the environment like the wallet passphrase
+fn spawn_stratum_server(node: Arc<RwLock<Node>>, network_tx: mpsc::Sender<NetworkCommand>, port: usize, public: bool){
+    let password = std::env::var(STRATUM_PASSWORD_VAR).ok().filter(|password| !password.is_empty());
+    tokio::spawn(async move {
+        if let Err(e) = start_stratum_server(node, network_tx, port, public, password).await{
+            error!("Stratum server failed: {}", e);
+        }
+    });
+}
+
+//only the serialization holds the node lock, the write happens off the runtime
+async fn save_mempool(node: &Arc<RwLock<Node>>) -> Result<()>{
+    let dump = node.read().await.dump_mempool()?;
+    tokio::task::spawn_blocking(move || Node::write_mempool(&dump)).await?
+}
+
+//the mempool is also dumped on shutdown, this limits what a crash can lose
+fn spawn_mempool_dump(node: Arc<RwLock<Node>>){
+    tokio::spawn(async move {
+        loop{
+            time::sleep(Duration::from_secs(MEMPOOL_DUMP_INTERVAL)).await;
+            if let Err(e) = save_mempool(&node).await{
+                warn!("Failed to save mempool: {}", e);
+            }
+        }
+    });
+}
+
+async fn bootstrap_node_main(node: Arc<RwLock<Node>>, stratum_port: Option<usize>, stratum_public: bool) -> Result<()>{
     info!("Starting Bootstrap Node");
 
      //initialising -----------------------------------------------------------------------------------
@@ -73,6 +111,12 @@ async fn bootstrap_node_main(node: Arc<RwLock<Node>>) -> Result<()>{
     });
 
 
+    if let Some(port) = stratum_port{
+        spawn_stratum_server(Arc::clone(&node), network_tx.clone(), port, stratum_public);
+    }
+
+    spawn_mempool_dump(Arc::clone(&node));
+
     tokio::signal::ctrl_c().await?;
     println!("");
     info!("Shutting down ...");
@@ -85,7 +129,7 @@ async fn bootstrap_node_main(node: Arc<RwLock<Node>>) -> Result<()>{
 
 
 
-async fn full_node_main(node: Arc<RwLock<Node>>) -> Result<()>{
+async fn full_node_main(node: Arc<RwLock<Node>>, stratum_port: Option<usize>, stratum_public: bool) -> Result<()>{
     info!("Starting full Node ...");
     
     //initialising -----------------------------------------------------------------------------------
@@ -147,6 +191,19 @@ async fn full_node_main(node: Arc<RwLock<Node>>) -> Result<()>{
         }
     });
 
+    //spawn wallet relock ticker
+    tokio::spawn({
+        let node = Arc::clone(&node);
+        async move {
+            loop{
+                time::sleep(Duration::from_secs(RELOCK_INTERVAL)).await;
+                for wallet in node.write().await.wallets.values_mut(){
+                    wallet.relock_if_expired();
+                }
+            }
+        }
+    });
+
     let network_tx_clone = network_tx.clone();
     if let Err(e) = network_tx_clone.send(NetworkCommand::Connect(format!("{}:{}", BOOTSTRAP_ADDR, BOOTSTRAP_PORT).parse()?)).await{
         warn!("unable to send on network channel: {}",e);
@@ -154,6 +211,12 @@ async fn full_node_main(node: Arc<RwLock<Node>>) -> Result<()>{
     
 
 
+    if let Some(port) = stratum_port{
+        spawn_stratum_server(Arc::clone(&node), network_tx.clone(), port, stratum_public);
+    }
+
+    spawn_mempool_dump(Arc::clone(&node));
+
     tokio::signal::ctrl_c().await?;
     println!("");
     info!("Shutting down ...");
@@ -165,21 +228,215 @@ async fn full_node_main(node: Arc<RwLock<Node>>) -> Result<()>{
     Ok(())
 }
 
+//an empty passphrase falls back to the key remembered in the OS keyring
+fn keystore_passphrase(passphrase: &str) -> Option<&str>{
+    if passphrase.is_empty() {None} else {Some(passphrase)}
+}
+
+async fn run_command(node: Arc<RwLock<Node>>, wallet: &str, command: Command, passphrase: Option<&str>) -> Result<()>{
+    match command{
+        Command::Rescan { from } => rescan_wallet(node, wallet, from).await?,
+        Command::ImportKey { key, rescan, from } => {
+            import_key(&node, wallet, &key, passphrase).await?;
+            if rescan{
+                rescan_wallet(node, wallet, from).await?;
+            }
+        }
+        Command::ExportKey { address } => {
+            println!("{}", export_key(&node, wallet, &address.parse::<Address>()?, passphrase.unwrap_or_default()).await?);
+        }
+        Command::Watch { address, rescan, from } => {
+            node.write().await.wallet_mut(wallet)?.watch(&address.parse::<Address>()?)?;
+            if rescan{
+                rescan_wallet(node, wallet, from).await?;
+            }
+        }
+        Command::SignMessage { address, message } => {
+            println!("{}", node.read().await.wallet(wallet)?.sign_message(&address.parse::<Address>()?, &message)?);
+        }
+        Command::VerifyMessage { address, message, signature } => {
+            let valid = verify_message(&address.parse::<Address>()?, &message, &signature)?;
+            println!("{}", if valid {"Signature is valid"} else {"Signature is NOT valid"});
+        }
+        Command::SignPsbt { psbt, yes } => {
+            let mut psbt = psbt.parse::<Psbt>()?;
+            print_psbt_summary(&psbt);
+            if !yes && !confirm("Sign this transaction?")?{
+                warn!("Transaction not signed");
+                return Ok(())
+            }
+            let signed = node.read().await.wallet(wallet)?.sign_psbt(&mut psbt)?;
+            info!("Signed {} inputs, complete: {}", signed, psbt.is_complete());
+            println!("{}", psbt);
+        }
+        Command::AbandonPsbt { psbt } => {
+            let psbt = psbt.parse::<Psbt>()?;
+            let released = node.write().await.wallet_mut(wallet)?.abandon_psbt(&psbt);
+            info!("Released {} reserved outputs", released);
+        }
+        Command::ListUtxos => {
+            let node_read = node.read().await;
+            for utxo in node_read.wallet(wallet)?.get_utxos(node_read.get_height()){
+                println!("{}", serde_json::to_string(&utxo)?);
+            }
+        }
+        Command::Freeze { outpoint } => node.write().await.wallet_mut(wallet)?.freeze(&parse_outpoint(&outpoint)?)?,
+        Command::Unfreeze { outpoint } => {
+            if !node.write().await.wallet_mut(wallet)?.unfreeze(&parse_outpoint(&outpoint)?){
+                warn!("Output was not frozen: {}", outpoint);
+            }
+        }
+        Command::CreateInvoice { amount, label, message, expiry } => {
+            let invoice = node.write().await.wallet_mut(wallet)?.create_invoice(amount, label, message, expiry);
+            info!("Created invoice: {}", invoice.invoice.id);
+            println!("{}", invoice.uri);
+        }
+        Command::ListInvoices => {
+            for invoice in node.read().await.wallet(wallet)?.get_invoices(){
+                println!("{}", serde_json::to_string(&invoice)?);
+            }
+        }
+        Command::CreateWallet { name } => {
+            let phrase = create_wallet(&node, &name, passphrase.unwrap_or_default()).await?;
+            print_recovery_phrase(&phrase);
+        }
+        Command::LoadWallet { name } => node.write().await.load_wallet(&name)?,
+        Command::UnloadWallet { name } => node.write().await.unload_wallet(&name)?,
+        Command::SetMiningWallet { name } => node.write().await.set_mining_wallet(&name)?,
+        Command::ListWallets => {
+            for info in node.read().await.list_wallets()?{
+                println!("{}", serde_json::to_string(&info)?);
+            }
+        }
+        Command::TestAccept { transaction } => {
+            let result = node.read().await.test_accept(&Transaction::from_hex(&transaction)?).await;
+            println!("{}", serde_json::to_string(&result)?);
+        }
+        Command::Prioritise { txid, fee_delta } => node.write().await.prioritise(&txid, fee_delta)?,
+    }
+    Ok(())
+}
+
+//kept off the command line so it does not show up in the process list or shell history
+fn read_passphrase(ask: bool) -> Result<String>{
+    if !ask{
+        return Ok(std::env::var(PASSPHRASE_VAR).unwrap_or_default())
+    }
+    read_secret("Wallet passphrase: ")
+}
+
+//never taken from the command line, where other users can read it in the process list
+fn read_mnemonic() -> Result<String>{
+    match std::env::var(MNEMONIC_VAR){
+        Ok(phrase) => Ok(phrase),
+        Err(_) => read_secret("Recovery phrase: "),
+    }
+}
+
+fn read_secret(prompt: &str) -> Result<String>{
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    let echo_off = set_echo(false);
+    let mut secret = String::new();
+    let result = io::stdin().read_line(&mut secret);
+    if echo_off{
+        set_echo(true);
+        eprintln!();
+    }
+    result?;
+    Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+}
+
+//best effort, the prompt still works where stty is unavailable
+fn set_echo(on: bool) -> bool{
+    std::process::Command::new("stty")
+        .arg(if on {"echo"} else {"-echo"})
+        .stdin(std::process::Stdio::inherit())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+//what the signature commits to, shown before anything is signed
+fn print_psbt_summary(psbt: &Psbt){
+    let change = psbt.get_change();
+    for (index, output) in psbt.get_transaction().outputs.iter().enumerate(){
+        let address = output.locking_script.P2PKHLocking_get_public_key_hash()
+            .and_then(|public_key_hash| Address::from_public_key_hash(public_key_hash).ok())
+            .map_or("non standard output".to_string(), |address| address.to_string());
+        let kind = if change.contains(&index) {" (change)"} else {""};
+        eprintln!("Output {}: {} to {}{}", index, output.value, address, kind);
+    }
+    match psbt.get_fee(){
+        Some(fee) => eprintln!("Fee: {}", fee),
+        None => eprintln!("Fee: unknown, some spent outputs are missing"),
+    }
+}
+
+fn confirm(question: &str) -> Result<bool>{
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+//printed straight to the terminal so the phrase never reaches the log
+fn print_recovery_phrase(phrase: &str){
+    println!("Write down the recovery phrase for the new wallet: {}", phrase);
+}
+
 pub async fn start_server() -> Result<()>{
     let args = Cli::parse();
 
+    let passphrase = read_passphrase(args.ask_passphrase)?;
+
     let mut node = match args.operation.as_str(){
-            "load" => Node::load().await?,
-            "new" => Node::new().await,
-            _ => {return Err(anyhow!("Error: Unknown node type '{}'. Use 'bootstrap' or 'full-node'", args.operation))}
+            "load" => {
+                let mut node = Node::load().await?;
+                let timeout = (args.unlock_timeout > 0).then_some(args.unlock_timeout);
+                let result = node.wallet_mut(&args.wallet)
+                    .and_then(|wallet| wallet.unlock(keystore_passphrase(&passphrase), timeout, false));
+                if let Err(e) = result{
+                    info!("Wallet is locked: {}", e);
+                }
+                node
+            }
+            "new" => {
+                check_unused_name(&args.wallet)?;
+                let (wallet, phrase) = Wallet::new(&args.wallet, &passphrase)?;
+                print_recovery_phrase(&phrase);
+                Node::new(wallet).await
+            }
+            "restore" => {
+                let phrase = read_mnemonic()?;
+                match Node::load().await{
+                    Ok(mut node) => {
+                        node.restore_wallet(&args.wallet, &phrase, &passphrase)?;
+                        node
+                    }
+                    Err(e) if e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) => {
+                        check_unused_name(&args.wallet)?;
+                        Node::new(Wallet::from_mnemonic(&args.wallet, &phrase, &passphrase)?).await
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            _ => {return Err(anyhow!("Error: Unknown operation '{}'. Use 'load', 'new' or 'restore'", args.operation))}
         };
 
     node.set_port(args.port);
+    node.set_mempool_limits(MempoolLimits { max_size: args.mempool_size, max_age: args.mempool_expiry });
     let node = Arc::new(RwLock::new(node));
+
+    if let Some(command) = args.command{
+        run_command(Arc::clone(&node), &args.wallet, command, keystore_passphrase(&passphrase)).await?;
+        node.read().await.save().await?;
+        return Ok(())
+    }
     
     match args.node_path.as_str(){
-        "bootstrap" => bootstrap_node_main(node).await?,
-        "full-node" => full_node_main(node).await?,
+        "bootstrap" => bootstrap_node_main(node, args.stratum_port, args.stratum_public).await?,
+        "full-node" => full_node_main(node, args.stratum_port, args.stratum_public).await?,
         _ => {
             return Err(anyhow!("Error: Unknown node type '{}'. Use 'bootstrap' or 'full-node'", args.node_path))
         }
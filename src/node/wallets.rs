@@ -0,0 +1,201 @@
+const WALLET_DIR: &str = "configs/wallets";
+
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::{Result, anyhow};
+
+use log::info;
+
+use serde::{Deserialize, Serialize};
+
+use tokio::{sync::RwLock, task};
+
+use super::node::Node;
+
+use crate::{
+    block::{Address, OpenSecrets, PublicKey, Wallet, WalletError},
+    ui::WalletInfo,
+};
+
+//the height lets a wallet catch up on blocks that arrived while it was unloaded
+#[derive(Serialize, Deserialize)]
+struct WalletFile{
+    height: Option<usize>,
+    wallet: Wallet,
+}
+
+fn wallet_path(name: &str) -> PathBuf{
+    PathBuf::from(WALLET_DIR).join(format!("{}.json", name))
+}
+
+//names become file names, so only allow characters that are safe in a path
+fn check_name(name: &str) -> Result<()>{
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'){
+        return Err(anyhow!("Invalid wallet name '{}', use letters, digits, '-' and '_'", name))
+    }
+    Ok(())
+}
+
+//keystores and wallet files outlive a loaded wallet, a name in use by either is never reused
+pub(super) fn check_unused_name(name: &str) -> Result<()>{
+    check_name(name)?;
+    if Wallet::exists(name) || wallet_path(name).exists(){
+        return Err(anyhow!("Wallet already exists: {}", name))
+    }
+    Ok(())
+}
+
+impl Node{
+    pub fn wallet(&self, name: &str) -> Result<&Wallet>{
+        self.wallets.get(name).ok_or(WalletError::NotLoaded(name.to_string()).into())
+    }
+
+    pub fn wallet_mut(&mut self, name: &str) -> Result<&mut Wallet>{
+        self.wallets.get_mut(name).ok_or(WalletError::NotLoaded(name.to_string()).into())
+    }
+
+    pub fn get_mining_wallet(&self) -> &Wallet{
+        self.wallets.get(&self.mining_wallet).expect("mining wallet is never unloaded")
+    }
+
+    pub fn set_mining_wallet(&mut self, name: &str) -> Result<()>{
+        self.wallet(name)?;
+        self.mining_wallet = name.to_string();
+        info!("Mining rewards go to wallet: {}", name);
+        Ok(())
+    }
+
+    pub(super) fn check_new_wallet(&self, name: &str) -> Result<()>{
+        if self.wallets.contains_key(name){
+            return Err(anyhow!("Wallet already exists: {}", name))
+        }
+        check_unused_name(name)
+    }
+
+    pub(super) fn add_new_wallet(&mut self, wallet: Wallet) -> Result<()>{
+        let name = wallet.get_name().to_string();
+        if self.wallets.contains_key(&name){
+            return Err(anyhow!("Wallet already exists: {}", name))
+        }
+        self.save_wallet(&wallet)?;
+        self.wallets.insert(name.clone(), wallet);
+        info!("Created wallet: {}", name);
+        Ok(())
+    }
+
+    pub fn load_wallet(&mut self, name: &str) -> Result<()>{
+        check_name(name)?;
+        if self.wallets.contains_key(name){
+            return Err(anyhow!("Wallet already loaded: {}", name))
+        }
+
+        let file = File::open(wallet_path(name)).map_err(|e| anyhow!("Unable to open wallet {}: {}", name, e))?;
+        let WalletFile{height, mut wallet} = serde_json::from_reader(file)?;
+
+        let start = height.map_or(0, |height| height + 1);
+        for block in self.block_chain.iter().skip(start){
+            wallet.add_block(block);
+        }
+        wallet.sync_mempool(&self.mempool);
+
+        self.wallets.insert(name.to_string(), wallet);
+        info!("Loaded wallet: {}", name);
+        Ok(())
+    }
+
+    pub fn unload_wallet(&mut self, name: &str) -> Result<()>{
+        if name == self.mining_wallet{
+            return Err(anyhow!("Wallet receives mining rewards, choose another mining wallet first"))
+        }
+        self.save_wallet(self.wallet(name)?)?;
+        self.wallets.remove(name);
+        info!("Unloaded wallet: {}", name);
+        Ok(())
+    }
+
+    pub fn save_wallet(&self, wallet: &Wallet) -> Result<()>{
+        fs::create_dir_all(WALLET_DIR)?;
+        let file = File::create(wallet_path(wallet.get_name()))?;
+        serde_json::to_writer(file, &WalletFile { height: self.get_height(), wallet: wallet.clone() })?;
+        Ok(())
+    }
+
+    //loaded wallets plus any saved in the wallet directory
+    pub fn list_wallets(&self) -> Result<Vec<WalletInfo>>{
+        let mut names: Vec<String> = self.wallets.keys().cloned().collect();
+        if let Ok(entries) = fs::read_dir(WALLET_DIR){
+            for entry in entries{
+                let path = entry?.path();
+                if path.extension().is_some_and(|extension| extension == "json")
+                    && let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+                    && !names.iter().any(|loaded| loaded == name){
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+
+        Ok(names.into_iter().map(|name| {
+            let wallet = self.wallets.get(&name);
+            let (loaded, locked) = (wallet.is_some(), wallet.is_none_or(|wallet| wallet.is_locked()));
+            let mining = name == self.mining_wallet;
+            WalletInfo::new(name, loaded, mining, locked)
+        }).collect())
+    }
+}
+
+//key derivation and the keyring are slow, so they run on the blocking pool before the node lock is taken
+
+//returns the recovery phrase of the new wallet
+pub async fn create_wallet(node: &Arc<RwLock<Node>>, name: &str, passphrase: &str) -> Result<String>{
+    node.read().await.check_new_wallet(name)?;
+
+    let (wallet, phrase) = {
+        let (name, passphrase) = (name.to_string(), passphrase.to_string());
+        task::spawn_blocking(move || {
+            if Wallet::exists(&name){
+                return Err(anyhow!("Wallet already exists: {}", name))
+            }
+            Wallet::new(&name, &passphrase)
+        }).await??
+    };
+
+    node.write().await.add_new_wallet(wallet)?;
+    Ok(phrase)
+}
+
+pub async fn unlock_wallet(
+    node: &Arc<RwLock<Node>>, 
+    name: &str, 
+    passphrase: Option<&str>, 
+    timeout: Option<u64>, 
+    remember: bool
+) -> Result<()>{
+    let secrets = open_secrets(node, name, passphrase, remember).await?;
+    node.write().await.wallet_mut(name)?.unlock_with(secrets, timeout)
+}
+
+pub async fn import_key(node: &Arc<RwLock<Node>>, name: &str, private_key: &str, passphrase: Option<&str>) -> Result<PublicKey>{
+    let secrets = open_secrets(node, name, passphrase, false).await?;
+    node.write().await.wallet_mut(name)?.import_key_with(private_key, secrets)
+}
+
+//the passphrase is checked on every export, an unlocked wallet or remembered key is not enough
+pub async fn export_key(node: &Arc<RwLock<Node>>, name: &str, address: &Address, passphrase: &str) -> Result<String>{
+    if passphrase.is_empty(){
+        return Err(anyhow!("Exporting a key requires the wallet passphrase"))
+    }
+    let secrets = open_secrets(node, name, Some(passphrase), false).await?;
+    node.read().await.wallet(name)?.export_key_with(address, &secrets)
+}
+
+async fn open_secrets(node: &Arc<RwLock<Node>>, name: &str, passphrase: Option<&str>, remember: bool) -> Result<OpenSecrets>{
+    node.read().await.wallet(name)?;
+
+    let (name, passphrase) = (name.to_string(), passphrase.map(str::to_string));
+    task::spawn_blocking(move || Wallet::open_secrets(&name, passphrase.as_deref(), remember)).await?
+}
@@ -6,18 +6,22 @@ use std::{
     fs::File, path::PathBuf, 
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 
 use log::info;
 
-use crate::block::OutputSpec;
+use crate::block::{Address, CoinSelection, OutputSpec, Psbt, PublicKey, TransactionPlan, TransactionWithFee, fee_rate, parse_outpoint};
 
 const FILE_PATH: &str = "configs/AddressBook.json";
 
+const DEFAULT_CONSOLIDATION_INPUTS: usize = 50;
+
 #[derive(Serialize)]
 pub struct NodeStatus{
     height: usize,
     mempool_size: usize,
+    mempool_bytes: usize,
+    min_fee_rate: usize,
     difficulty: usize,
 }
 
@@ -25,11 +29,15 @@ impl NodeStatus{
     pub fn new(
         height: usize,
         mempool_size: usize,
+        mempool_bytes: usize,
+        min_fee_rate: usize,
         difficulty: usize
     ) -> Self{
         Self{
             height,
             mempool_size,
+            mempool_bytes,
+            min_fee_rate,
             difficulty
         }
     }
@@ -38,23 +46,222 @@ impl NodeStatus{
 
 #[derive(Serialize)]
 pub struct UserStatus{
-    amount: usize,
-    pk: String
+    confirmed: usize,
+    pending: usize,
+    available: usize,
+    watch_only: usize,
+    address: String,
+    locked: bool,
 }
 
 impl UserStatus{
-    pub fn new(amount: usize, pk: String) -> Self{
+    pub fn new(
+        confirmed: usize, 
+        pending: usize, 
+        available: usize, 
+        watch_only: usize,
+        address: String, 
+        locked: bool
+    ) -> Self{
         Self { 
-            amount, 
-            pk 
+            confirmed, 
+            pending,
+            available,
+            watch_only,
+            address,
+            locked,
         }
     }
 }
 
+#[derive(Serialize)]
+pub struct WalletInfo{
+    name: String,
+    loaded: bool,
+    mining: bool,
+    locked: bool,
+}
+
+impl WalletInfo{
+    pub fn new(name: String, loaded: bool, mining: bool, locked: bool) -> Self{
+        Self { 
+            name, 
+            loaded, 
+            mining, 
+            locked 
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWalletRequest{
+    pub name: String,
+    #[serde(default)]
+    pub passphrase: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WalletNameRequest{
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LabelRequest{
+    pub txid: String,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery{
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat{
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeeBumpRequest{
+    pub txid: String,
+    pub fee: Option<usize>,
+    pub target: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FreezeRequest{
+    pub outpoints: Vec<String>,
+    pub frozen: bool,
+}
+
+//max_rate skips the sweep while the cheapest fee estimate is above it
+#[derive(Debug, Deserialize)]
+pub struct ConsolidateRequest{
+    pub threshold: usize,
+    #[serde(default = "default_consolidation_inputs")]
+    pub max_inputs: usize,
+    pub max_rate: Option<usize>,
+}
+
+fn default_consolidation_inputs() -> usize{
+    DEFAULT_CONSOLIDATION_INPUTS
+}
+
+#[derive(Debug, Serialize)]
+pub struct PendingTransaction{
+    txid: String,
+    fee: usize,
+    fee_rate: usize,
+    size: usize,
+    replaceable: bool,
+}
+
+impl From<TransactionWithFee> for PendingTransaction{
+    fn from(TransactionWithFee{transaction, fee, ..}: TransactionWithFee) -> Self{
+        let size = transaction.get_size();
+        Self { 
+            txid: hex::encode(transaction.get_hash()), 
+            fee, 
+            fee_rate: fee_rate(fee, size), 
+            size, 
+            replaceable: transaction.is_replaceable() 
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RescanRequest{
+    #[serde(default)]
+    pub from_height: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportKeyRequest{
+    pub private_key: String,
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub rescan: bool,
+    #[serde(default)]
+    pub from_height: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeeEstimateQuery{
+    pub target: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddressQuery{
+    pub address: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UriQuery{
+    pub uri: String,
+}
+
+//expiry is in seconds from now
+#[derive(Debug, Deserialize)]
+pub struct InvoiceRequest{
+    pub amount: Option<usize>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    pub expiry: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InvoicePath{
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignMessageRequest{
+    pub address: String,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyMessageRequest{
+    pub address: String,
+    pub message: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WatchRequest{
+    pub address: String,
+    #[serde(default)]
+    pub rescan: bool,
+    #[serde(default)]
+    pub from_height: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnlockRequest{
+    pub passphrase: Option<String>,
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub remember: bool,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TransactionRequest{
     pub recipients: Vec<(String, usize)>,
-    pub fee: usize,
+    pub fee: Option<usize>,
+    pub target: Option<usize>,
+    #[serde(default)]
+    pub strategy: CoinSelection,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub replaceable: bool,
+    //outpoints chosen by hand, empty lets the coin selection strategy choose
+    #[serde(default)]
+    pub inputs: Vec<String>,
 }
 
 impl TransactionRequest{
@@ -64,30 +271,124 @@ impl TransactionRequest{
         for (recipient, value) in self.recipients.iter(){
             info!("\t\t{}:{}",recipient, value);
         }
-        info!("\tFee:{}", self.fee);
-    }
-
-    pub fn calculate_total_spend(&self) -> usize{
-        self.recipients.iter().map(
-            |(_r, value)| value
-        ).sum::<usize>() 
-        + self.fee
+        match self.fee{
+            Some(fee) => info!("\tFee:{}", fee),
+            None => info!("\tFee: estimated"),
+        }
     }
 
     pub fn get_outputs(&self) -> Result<Vec<OutputSpec>> {
     self.recipients.iter().map(
         |(recipient, value)| {
-            Ok(OutputSpec::new(*value, hex::decode(recipient)?))
+            let address: Address = recipient.parse()
+                .map_err(|e| anyhow!("Invalid recipient '{}': {}", recipient, e))?;
+            Ok(OutputSpec::new(*value, address))
         }
     ).collect()
 
     }
+
+    pub fn get_inputs(&self) -> Result<Vec<(Vec<u8>, usize)>>{
+        self.inputs.iter().map(|outpoint| parse_outpoint(outpoint)).collect()
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct TransactionResponse{
     pub success: bool,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<TransactionPlan>,
+}
+
+impl TransactionResponse{
+    pub fn success(message: &str) -> Self{
+        Self { 
+            success: true, 
+            message: message.to_string(), 
+            plan: None 
+        }
+    }
+
+    pub fn failure(message: &str) -> Self{
+        Self { 
+            success: false, 
+            message: message.to_string(), 
+            plan: None 
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PsbtCreateRequest{
+    #[serde(flatten)]
+    pub transaction: TransactionRequest,
+    #[serde(default)]
+    pub watch_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PsbtRequest{
+    pub psbt: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitBlockRequest{
+    pub block: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawTransactionRequest{
+    pub transaction: String,
+}
+
+//a positive delta makes the transaction look more attractive to our miner, negative less
+#[derive(Debug, Deserialize)]
+pub struct PrioritiseRequest{
+    pub txid: String,
+    pub fee_delta: isize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PsbtCombineRequest{
+    pub psbts: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PsbtResponse{
+    pub success: bool,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psbt: Option<String>,
+    pub fee: Option<usize>,
+    pub complete: bool,
+    //raw transaction once every input is signed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<String>,
+}
+
+impl PsbtResponse{
+    pub fn new(message: &str, psbt: &Psbt) -> Self{
+        Self { 
+            success: true, 
+            message: message.to_string(), 
+            psbt: Some(psbt.to_string()), 
+            fee: psbt.get_fee(), 
+            complete: psbt.is_complete(),
+            transaction: psbt.finalize().ok().map(|transaction| transaction.to_hex()),
+        }
+    }
+
+    pub fn failure(message: &str) -> Self{
+        Self { 
+            success: false, 
+            message: message.to_string(), 
+            psbt: None, 
+            fee: None, 
+            complete: false,
+            transaction: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -99,8 +400,8 @@ impl AddressBook{
     }
     pub fn load() -> Self{
         if let Ok(file) = File::open(FILE_PATH){
-            match serde_json::from_reader(file){
-                Ok(address_book) => address_book,
+            match serde_json::from_reader::<_, AddressBook>(file){
+                Ok(address_book) => address_book.upgrade_public_keys(),
                 Err(e) => {
                     warn!("Unable to read from addresss book: {}", e);
                     AddressBook::new()
@@ -112,6 +413,24 @@ impl AddressBook{
         }
     }
 
+    //older address books stored hex public keys instead of addresses
+    fn upgrade_public_keys(self) -> Self{
+        Self(self.0.into_iter().map(|(label, address)| {
+            let upgraded = hex::decode(&address).ok()
+                .and_then(|bytes| PublicKey::from_bytes(bytes).ok())
+                .map(|public_key| public_key.to_address().to_string());
+            (label, upgraded.unwrap_or(address))
+        }).collect())
+    }
+
+    pub fn validate(&self) -> Result<()>{
+        for (label, address) in self.0.iter(){
+            address.parse::<Address>()
+                .map_err(|e| anyhow!("Invalid address for '{}': {}", label, e))?;
+        }
+        Ok(())
+    }
+
     pub fn save(&self){
         let file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
             .join(FILE_PATH);
@@ -3,4 +3,4 @@ mod api_messages;
 
 pub use server::start_ui_server;
 
-pub use api_messages::{NodeStatus, UserStatus};
\ No newline at end of file
+pub use api_messages::{NodeStatus, UserStatus, WalletInfo};
\ No newline at end of file
@@ -1,4 +1,6 @@
 use std::{ 
+    convert::Infallible,
+    net::SocketAddr,
     path::PathBuf, sync::{Arc, 
         atomic::{AtomicBool, Ordering}
     }
@@ -11,22 +13,34 @@ use tokio::{
     net::TcpListener,
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 
 use crate::node::{
     Node,
-    NetworkCommand
+    NetworkCommand,
+    RescanStatus,
+    create_wallet as create_node_wallet,
+    import_key as import_wallet_key,
+    rescan_wallet,
+    unlock_wallet as unlock_node_wallet,
 };
 
 use axum::{
     Router,
     Json,
-    response::Html,
+    response::{Html, IntoResponse, Response},
     routing::{get, post},
-    extract::State,
+    extract::{ConnectInfo, FromRequestParts, Path, Query, RawPathParams, State},
+    http::{StatusCode, header, request::Parts},
 };
 
-use super::api_messages::{AddressBook, TransactionRequest, TransactionResponse, UserStatus, NodeStatus};
+use super::api_messages::{
+    AddressBook, ConsolidateRequest, CreateWalletRequest, FreezeRequest, WalletInfo, WalletNameRequest, AddressQuery, ExportFormat, FeeBumpRequest, FeeEstimateQuery, ExportQuery, ImportKeyRequest, InvoicePath, InvoiceRequest, UriQuery, 
+    LabelRequest, PendingTransaction, PrioritiseRequest, PsbtCombineRequest, PsbtCreateRequest, RawTransactionRequest, PsbtRequest, PsbtResponse, RescanRequest, SubmitBlockRequest, SignMessageRequest, VerifyMessageRequest, TransactionRequest, TransactionResponse, UnlockRequest, UserStatus, NodeStatus, 
+    WatchRequest
+};
+
+use crate::{block::{Address, Block, BlockTemplate, DEFAULT_TARGET, DEFAULT_WALLET, Fee, FeeEstimate, HistoryView, InvoiceView, PaymentUri, Psbt, Transaction, UtxoView, WalletError, parse_outpoint, verify_message}, utils::get_timestamp};
 
 use tower_http::services::ServeDir;
 
@@ -47,18 +61,64 @@ pub async fn start_ui_server(
         save,
     };
 
+    //served under /api for the default wallet and under /api/wallets/{wallet} for any loaded wallet
+    let wallet_routes = Router::new()
+        .route("/transaction", post(submit_transaction))
+        .route("/transaction/bump", post(bump_fee))
+        .route("/psbt/create", post(create_psbt))
+        .route("/psbt/sign", post(sign_psbt))
+        .route("/psbt/abandon", post(abandon_psbt))
+        .route("/user_status", get(get_user_status))
+        .route("/new_address", post(new_address))
+        .route("/wallet/unlock", post(unlock_wallet))
+        .route("/wallet/lock", post(lock_wallet))
+        .route("/wallet/forget", post(forget_wallet_key))
+        .route("/wallet/rescan", post(start_rescan))
+        .route("/wallet/pending", get(get_pending_transactions))
+        .route("/utxos", get(get_utxos))
+        .route("/utxos/freeze", post(freeze_utxos))
+        .route("/utxos/consolidate", post(consolidate))
+        .route("/wallet/keys", get(get_wallet_keys))
+        .route("/wallet/import_key", post(import_key))
+        .route("/wallet/watch", post(watch_address))
+        .route("/message/sign", post(sign_message))
+        .route("/history", get(get_history))
+        .route("/history/label", post(set_history_label))
+        .route("/history/export", get(export_history))
+        .route("/invoices", get(get_invoices))
+        .route("/invoices", post(create_invoice))
+        .route("/invoices/{id}", get(get_invoice));
+
     let app = Router::new()
         .route("/", get(index))
-        .route("/api/transaction", post(submit_transaction))
+        .nest("/api", wallet_routes.clone())
+        .nest("/api/wallets/{wallet}", wallet_routes)
+        .route("/api/wallets", get(list_wallets))
+        .route("/api/wallets/create", post(create_wallet))
+        .route("/api/wallets/load", post(load_wallet))
+        .route("/api/wallets/unload", post(unload_wallet))
+        .route("/api/wallets/mining", post(set_mining_wallet))
+        .route("/api/psbt/update", post(update_psbt))
+        .route("/api/psbt/combine", post(combine_psbts))
+        .route("/api/psbt/broadcast", post(broadcast_psbt))
         .route("/api/node_status", get(get_node_status))
-        .route("/api/user_status", get(get_user_status))
+        .route("/api/rescan", get(get_rescan_status))
+        .route("/api/fee_estimate", get(get_fee_estimate))
+        .route("/api/mempool/test_accept", post(test_accept))
+        .route("/api/mempool/prioritise", post(prioritise_transaction))
+        .route("/api/mining/template", get(get_block_template))
+        .route("/api/mining/submit", post(submit_block))
+        .route("/api/message/verify", post(verify_signed_message))
         .route("/api/address_book", get(get_address_book))
         .route("/api/address_book", post(save_address_book))
+        .route("/api/validate_address", get(validate_address))
+        .route("/api/parse_uri", get(parse_uri))
         .route("/api/save_check", get(check_save_request))
         .nest_service("/static", ServeDir::new(static_dir))
         .with_state(state);
 
-    let addr= "0.0.0.0:3000";
+    //wallet endpoints have no authentication, so only local connections are accepted
+    let addr= "127.0.0.1:3000";
 
     let listener = TcpListener::bind(addr).await?;
 
@@ -69,7 +129,7 @@ pub async fn start_ui_server(
         warn!("Failed to open browser: {}",e);
     }
 
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
@@ -81,6 +141,45 @@ struct AppState{
     save: Arc<AtomicBool>
 }
 
+//routes without a wallet in the path use the default wallet
+struct WalletName(String);
+
+impl<S: Send + Sync> FromRequestParts<S> for WalletName{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection>{
+        let name = RawPathParams::from_request_parts(parts, state).await.ok()
+            .and_then(|params| params.iter()
+                .find(|(key, _value)| *key == "wallet")
+                .map(|(_key, value)| value.to_string()));
+        Ok(Self(name.unwrap_or(DEFAULT_WALLET.to_string())))
+    }
+}
+
+//a wallet that is not loaded is not found, a locked one conflicts, anything else is a bad request
+struct ApiError(StatusCode, anyhow::Error);
+
+impl IntoResponse for ApiError{
+    fn into_response(self) -> Response{
+        (
+            self.0, 
+            Json(serde_json::json!({"success": false, "message": self.1.to_string()}))
+        ).into_response()
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for ApiError{
+    fn from(e: E) -> Self{
+        let e = e.into();
+        let status = match e.downcast_ref::<WalletError>(){
+            Some(WalletError::NotLoaded(_)) => StatusCode::NOT_FOUND,
+            Some(WalletError::Locked) => StatusCode::CONFLICT,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        Self(status, e)
+    }
+}
+
     
 async fn check_save_request(State(state): State<AppState>) -> Json<serde_json::Value>{
     let should_save = state.save.swap(false, Ordering::SeqCst);
@@ -94,52 +193,584 @@ async fn get_address_book() -> Json<AddressBook>{
 async fn save_address_book(
     Json(address_book): Json<AddressBook>
 ) -> Json<serde_json::Value>{
+    if let Err(e) = address_book.validate(){
+        return Json(serde_json::json!({"success": false, "message": e.to_string()}))
+    }
     address_book.save();
     Json(serde_json::json!({"success": true}))
 }
 
+async fn validate_address(Query(query): Query<AddressQuery>) -> Json<serde_json::Value>{
+    match query.address.parse::<Address>(){
+        Ok(_) => Json(serde_json::json!({"success": true})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    }
+}
+
+async fn parse_uri(Query(query): Query<UriQuery>) -> Json<serde_json::Value>{
+    match query.uri.parse::<PaymentUri>(){
+        Ok(uri) => Json(serde_json::json!({
+            "success": true, 
+            "expired": uri.is_expired(get_timestamp()), 
+            "uri": uri
+        })),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    }
+}
 
 async fn submit_transaction(
     State(state): State<AppState>, 
+    WalletName(wallet): WalletName,
     Json(req): Json<TransactionRequest>
-) -> Json<TransactionResponse>{
+) -> Result<Json<TransactionResponse>, ApiError>{
     req.log();
+    
+    let (outputs, inputs) = match req.get_outputs().and_then(|outputs| Ok((outputs, req.get_inputs()?))){
+        Ok(parsed) => parsed,
+        Err(e) => return Ok(Json(TransactionResponse::failure(&e.to_string())))
+    };
 
-    if req.calculate_total_spend() > state.node.read().await.wallet.get_funds(){
-        return Json(TransactionResponse {
-            success: false,
-            message: "Insufficient Funds".to_string()
+    let fee = match req.fee{
+        Some(fee) => Fee::Absolute(fee),
+        None => {
+            let estimate = state.node.read().await.estimate_fee(req.target.unwrap_or(DEFAULT_TARGET));
+            info!("Using estimated fee rate: {}", estimate.fee_rate);
+            Fee::Rate(estimate.fee_rate)
+        }
+    };
+
+    if req.dry_run{
+        return Ok(match state.node.read().await.wallet(&wallet)?.plan_transaction(&outputs, fee, req.strategy, &inputs){
+            Ok(plan) => Json(TransactionResponse { 
+                success: true, 
+                message: "Dry run, transaction not broadcasted".to_string(), 
+                plan: Some(plan) 
+            }),
+            Err(e) => Json(TransactionResponse::failure(&e.to_string())),
         })
     }
-    
-    let Ok(outputs) = req.get_outputs()else{
-        return Json(TransactionResponse {
-            success: false,
-            message: "Contains invalid addressess".to_string()
-        })
+
+    let transaction = {
+        let mut node_write = state.node.write().await;
+        let version = node_write.get_version();
+        node_write.wallet_mut(&wallet)?.new_transaction(version, outputs, fee, req.strategy, req.replaceable, &inputs)
     };
+    Ok(broadcast(&state, transaction.map_err(Into::into), "Transaction being broadcasted").await)
+}
 
+async fn bump_fee(
+    State(state): State<AppState>, 
+    WalletName(wallet): WalletName,
+    Json(req): Json<FeeBumpRequest>
+) -> Json<TransactionResponse>{
     let transaction = {
-        let node_read = state.node.read().await;
-        node_read.wallet.new_transaction(node_read.get_version(), outputs, req.fee)
+        let mut node_write = state.node.write().await;
+        let fee = match req.fee{
+            Some(fee) => Fee::Absolute(fee),
+            None => Fee::Rate(node_write.estimate_fee(req.target.unwrap_or(DEFAULT_TARGET)).fee_rate),
+        };
+        node_write.bump_fee(&wallet, &req.txid, fee)
+    };
+    broadcast(&state, transaction, "Replacement being broadcasted").await
+}
+
+async fn consolidate(
+    State(state): State<AppState>, 
+    WalletName(wallet): WalletName,
+    Json(req): Json<ConsolidateRequest>
+) -> Json<TransactionResponse>{
+    let transaction = state.node.write().await.consolidate(&wallet, req.threshold, req.max_inputs, req.max_rate);
+    broadcast(&state, transaction, "Consolidation transaction being broadcasted").await
+}
+
+async fn get_utxos(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName
+) -> Result<Json<Vec<UtxoView>>, ApiError>{
+    let node_read = state.node.read().await;
+    Ok(Json(node_read.wallet(&wallet)?.get_utxos(node_read.get_height())))
+}
+
+async fn freeze_utxos(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Json(req): Json<FreezeRequest>
+) -> Result<Json<serde_json::Value>, ApiError>{
+    let mut node_write = state.node.write().await;
+    let wallet = node_write.wallet_mut(&wallet)?;
+
+    let result = req.outpoints.iter().try_for_each(|outpoint| {
+        let outpoint = parse_outpoint(outpoint)?;
+        if req.frozen{
+            wallet.freeze(&outpoint)?;
+        }else{
+            wallet.unfreeze(&outpoint);
+        }
+        Ok::<_, anyhow::Error>(())
+    });
+    Ok(match result{
+        Ok(()) => Json(serde_json::json!({"success": true})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    })
+}
+
+async fn broadcast(state: &AppState, transaction: Result<Transaction>, message: &str) -> Json<TransactionResponse>{
+    let transaction = match transaction{
+        Ok(transaction) => transaction,
+        Err(e) => {
+            warn!("Unable to create transaction: {}", e);
+            return Json(TransactionResponse::failure(&e.to_string()))
+        }
     };
 
     if let Err(e) = state.network_tx.send(NetworkCommand::Transaction(transaction)).await{
         warn!("Error Sending network command: {}", e);
     };
+    Json(TransactionResponse::success(message))
+}
+
+async fn create_psbt(
+    State(state): State<AppState>, 
+    WalletName(wallet): WalletName,
+    Json(PsbtCreateRequest{transaction: req, watch_only}): Json<PsbtCreateRequest>
+) -> Result<Json<PsbtResponse>, ApiError>{
+    req.log();
+
+    let outputs = match req.get_outputs(){
+        Ok(outputs) => outputs,
+        Err(e) => return Ok(Json(PsbtResponse::failure(&e.to_string())))
+    };
 
-    Json(TransactionResponse { 
-        success: true, 
-        message: "Transaction being broadcasted".to_string()
+    let mut node_write = state.node.write().await;
+    let fee = match req.fee{
+        Some(fee) => Fee::Absolute(fee),
+        None => Fee::Rate(node_write.estimate_fee(req.target.unwrap_or(DEFAULT_TARGET)).fee_rate),
+    };
+    let version = node_write.get_version();
+
+    Ok(match node_write.wallet_mut(&wallet)?.create_psbt(version, outputs, fee, req.strategy, req.replaceable, watch_only){
+        Ok(psbt) => Json(PsbtResponse::new("Partially signed transaction created", &psbt)),
+        Err(e) => Json(PsbtResponse::failure(&e.to_string())),
+    })
+}
+
+async fn update_psbt(
+    State(state): State<AppState>, 
+    Json(req): Json<PsbtRequest>
+) -> Json<PsbtResponse>{
+    let mut psbt = match req.psbt.parse::<Psbt>(){
+        Ok(psbt) => psbt,
+        Err(e) => return Json(PsbtResponse::failure(&e.to_string()))
+    };
+    let added = state.node.read().await.update_psbt(&mut psbt).await;
+    Json(PsbtResponse::new(&format!("Added {} spent outputs", added), &psbt))
+}
+
+async fn sign_psbt(
+    State(state): State<AppState>, 
+    WalletName(wallet): WalletName,
+    Json(req): Json<PsbtRequest>
+) -> Result<Json<PsbtResponse>, ApiError>{
+    let mut psbt = match req.psbt.parse::<Psbt>(){
+        Ok(psbt) => psbt,
+        Err(e) => return Ok(Json(PsbtResponse::failure(&e.to_string())))
+    };
+    Ok(match state.node.read().await.wallet(&wallet)?.sign_psbt(&mut psbt){
+        Ok(signed) => Json(PsbtResponse::new(&format!("Signed {} inputs", signed), &psbt)),
+        Err(e) => Json(PsbtResponse::failure(&e.to_string())),
     })
 }
 
+async fn abandon_psbt(
+    State(state): State<AppState>, 
+    WalletName(wallet): WalletName,
+    Json(req): Json<PsbtRequest>
+) -> Result<Json<TransactionResponse>, ApiError>{
+    let psbt = match req.psbt.parse::<Psbt>(){
+        Ok(psbt) => psbt,
+        Err(e) => return Ok(Json(TransactionResponse::failure(&e.to_string())))
+    };
+    let released = state.node.write().await.wallet_mut(&wallet)?.abandon_psbt(&psbt);
+    Ok(Json(TransactionResponse::success(&format!("Released {} reserved outputs", released))))
+}
+
+async fn combine_psbts(Json(req): Json<PsbtCombineRequest>) -> Json<PsbtResponse>{
+    let psbts = match req.psbts.iter().map(|psbt| psbt.parse::<Psbt>()).collect::<Result<Vec<Psbt>, _>>(){
+        Ok(psbts) => psbts,
+        Err(e) => return Json(PsbtResponse::failure(&e.to_string()))
+    };
+
+    let mut psbts = psbts.into_iter();
+    let Some(mut combined) = psbts.next() else{
+        return Json(PsbtResponse::failure("No partially signed transactions given"))
+    };
+    for psbt in psbts{
+        if let Err(e) = combined.combine(psbt){
+            return Json(PsbtResponse::failure(&e.to_string()))
+        }
+    }
+    Json(PsbtResponse::new("Partially signed transactions combined", &combined))
+}
+
+async fn broadcast_psbt(
+    State(state): State<AppState>, 
+    Json(req): Json<PsbtRequest>
+) -> Json<TransactionResponse>{
+    let transaction = req.psbt.parse::<Psbt>()
+        .and_then(|psbt| psbt.finalize())
+        .map_err(Into::into);
+    broadcast(&state, transaction, "Transaction being broadcasted").await
+}
+
+async fn get_pending_transactions(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName
+) -> Result<Json<Vec<PendingTransaction>>, ApiError>{
+    let pending = state.node.read().await.get_pending_transactions(&wallet)?;
+    Ok(Json(pending.into_iter().map(PendingTransaction::from).collect()))
+}
+
 async fn get_node_status(State(state): State<AppState>) -> Json<NodeStatus>{
     Json(state.node.read().await.get_node_status())
 }
 
-async fn get_user_status(State(state): State<AppState>) -> Json<UserStatus>{
-    Json(state.node.read().await.get_user_status())
+async fn get_fee_estimate(
+    State(state): State<AppState>,
+    Query(query): Query<FeeEstimateQuery>
+) -> Json<Vec<FeeEstimate>>{
+    let node_read = state.node.read().await;
+    let targets = match query.target{
+        Some(target) => vec![target],
+        None => vec![1, 2, 3, 6, 12, 25],
+    };
+    Json(targets.into_iter().map(|target| node_read.estimate_fee(target)).collect())
+}
+
+async fn test_accept(
+    State(state): State<AppState>,
+    Json(req): Json<RawTransactionRequest>
+) -> Json<serde_json::Value>{
+    let transaction = match Transaction::from_hex(&req.transaction){
+        Ok(transaction) => transaction,
+        Err(e) => return Json(serde_json::json!({"success": false, "message": format!("Invalid raw transaction: {}", e)})),
+    };
+    let result = state.node.read().await.test_accept(&transaction).await;
+    Json(serde_json::json!({"success": true, "result": result}))
+}
+
+//changes what our miner picks, so it stays local even if the ui is ever bound wider
+async fn prioritise_transaction(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    Json(req): Json<PrioritiseRequest>
+) -> Json<serde_json::Value>{
+    if !peer.ip().is_loopback(){
+        return Json(serde_json::json!({"success": false, "message": "Transactions can only be prioritised from this machine"}))
+    }
+    match state.node.write().await.prioritise(&req.txid, req.fee_delta){
+        Ok(()) => Json(serde_json::json!({"success": true})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    }
+}
+
+async fn get_block_template(State(state): State<AppState>) -> Json<BlockTemplate>{
+    Json(state.node.write().await.get_block_template().await)
+}
+
+//checked here so the miner gets the reason, the network handler adds and relays it
+async fn submit_block(
+    State(state): State<AppState>,
+    Json(req): Json<SubmitBlockRequest>
+) -> Json<serde_json::Value>{
+    let block = match Block::from_hex(&req.block){
+        Ok(block) => block,
+        Err(e) => return Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    };
+    if let Err(e) = state.node.read().await.check_block(&block).await{
+        return Json(serde_json::json!({"success": false, "message": e.to_string()}))
+    }
+
+    info!("Block {} submitted by external miner", block.get_height());
+    if let Err(e) = state.network_tx.send(NetworkCommand::Block(block)).await{
+        warn!("Error Sending network command: {}", e);
+        return Json(serde_json::json!({"success": false, "message": e.to_string()}))
+    }
+    Json(serde_json::json!({"success": true, "message": "Block accepted"}))
+}
+
+async fn get_user_status(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName
+) -> Result<Json<UserStatus>, ApiError>{
+    Ok(Json(state.node.read().await.get_user_status(&wallet)?))
+}
+
+async fn new_address(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName
+) -> Result<Json<serde_json::Value>, ApiError>{
+    let public_key = state.node.write().await.wallet_mut(&wallet)?.get_new_address();
+    Ok(Json(serde_json::json!({"address": public_key.to_address()})))
+}
+
+async fn unlock_wallet(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Json(req): Json<UnlockRequest>
+) -> Result<Json<serde_json::Value>, ApiError>{
+    let result = unlock_node_wallet(
+        &state.node,
+        &wallet,
+        req.passphrase.as_deref(), 
+        req.timeout, 
+        req.remember
+    ).await;
+    Ok(match result{
+        Ok(()) => Json(serde_json::json!({"success": true})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    })
+}
+
+async fn lock_wallet(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName
+) -> Result<Json<serde_json::Value>, ApiError>{
+    state.node.write().await.wallet_mut(&wallet)?.lock();
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+async fn forget_wallet_key(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName
+) -> Result<Json<serde_json::Value>, ApiError>{
+    state.node.read().await.wallet(&wallet)?.forget_remembered_key();
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+async fn get_rescan_status(State(state): State<AppState>) -> Json<RescanStatus>{
+    Json(state.node.read().await.rescan_progress.get_status())
+}
+
+async fn start_rescan(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Json(req): Json<RescanRequest>
+) -> Result<Json<serde_json::Value>, ApiError>{
+    {
+        let node_read = state.node.read().await;
+        node_read.wallet(&wallet)?;
+        if node_read.rescan_progress.get_status().running{
+            return Ok(Json(serde_json::json!({"success": false, "message": "A rescan is already running"})))
+        }
+    }
+
+    spawn_rescan(&state.node, wallet, req.from_height);
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+fn spawn_rescan(node: &Arc<RwLock<Node>>, wallet: String, from_height: usize){
+    tokio::spawn({
+        let node = Arc::clone(node);
+        async move {
+            if let Err(e) = rescan_wallet(node, &wallet, from_height).await{
+                warn!("Rescan failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn get_wallet_keys(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName
+) -> Result<Json<serde_json::Value>, ApiError>{
+    let node_read = state.node.read().await;
+    let wallet = node_read.wallet(&wallet)?;
+    Ok(Json(serde_json::json!({
+        "imported": wallet.get_imported_keys(),
+        "watched": wallet.get_watched(),
+    })))
+}
+
+async fn import_key(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Json(req): Json<ImportKeyRequest>
+) -> Result<Json<serde_json::Value>, ApiError>{
+    let result = import_wallet_key(
+        &state.node,
+        &wallet,
+        &req.private_key, 
+        req.passphrase.as_deref()
+    ).await;
+    Ok(match result{
+        Ok(public_key) => {
+            if req.rescan{
+                spawn_rescan(&state.node, wallet, req.from_height);
+            }
+            Json(serde_json::json!({"success": true, "address": public_key.to_address()}))
+        }
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    })
+}
+
+async fn sign_message(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Json(req): Json<SignMessageRequest>
+) -> Result<Json<serde_json::Value>, ApiError>{
+    let result = match req.address.parse::<Address>(){
+        Ok(address) => state.node.read().await.wallet(&wallet)?.sign_message(&address, &req.message),
+        Err(e) => Err(e.into()),
+    };
+    Ok(match result{
+        Ok(signature) => Json(serde_json::json!({"success": true, "signature": signature})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    })
+}
+
+async fn verify_signed_message(Json(req): Json<VerifyMessageRequest>) -> Json<serde_json::Value>{
+    let result = match req.address.parse::<Address>(){
+        Ok(address) => verify_message(&address, &req.message, &req.signature),
+        Err(e) => Err(e.into()),
+    };
+    match result{
+        Ok(valid) => Json(serde_json::json!({"success": true, "valid": valid})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    }
+}
+
+async fn watch_address(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Json(req): Json<WatchRequest>
+) -> Result<Json<serde_json::Value>, ApiError>{
+    let result = match req.address.parse::<Address>(){
+        Ok(address) => state.node.write().await.wallet_mut(&wallet)?.watch(&address),
+        Err(e) => Err(e.into()),
+    };
+    Ok(match result{
+        Ok(()) => {
+            if req.rescan{
+                spawn_rescan(&state.node, wallet, req.from_height);
+            }
+            Json(serde_json::json!({"success": true}))
+        }
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    })
+}
+
+async fn get_history(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName
+) -> Result<Json<Vec<HistoryView>>, ApiError>{
+    let node_read = state.node.read().await;
+    Ok(Json(node_read.wallet(&wallet)?.get_history(node_read.get_height())))
+}
+
+async fn set_history_label(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Json(req): Json<LabelRequest>
+) -> Result<Json<serde_json::Value>, ApiError>{
+    let found = state.node.write().await.wallet_mut(&wallet)?.set_label(&req.txid, req.label);
+    Ok(Json(serde_json::json!({"success": found})))
+}
+
+async fn export_history(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Query(query): Query<ExportQuery>
+) -> Result<Response, ApiError>{
+    let node_read = state.node.read().await;
+    let wallet = node_read.wallet(&wallet)?;
+    Ok(match query.format{
+        ExportFormat::Json => (
+            [(header::CONTENT_DISPOSITION, "attachment; filename=\"history.json\"")],
+            Json(wallet.get_history(node_read.get_height()))
+        ).into_response(),
+        ExportFormat::Csv => (
+            [
+                (header::CONTENT_TYPE, "text/csv"),
+                (header::CONTENT_DISPOSITION, "attachment; filename=\"history.csv\"")
+            ],
+            wallet.export_history_csv(node_read.get_height())
+        ).into_response(),
+    })
+}
+
+async fn get_invoices(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName
+) -> Result<Json<Vec<InvoiceView>>, ApiError>{
+    Ok(Json(state.node.read().await.wallet(&wallet)?.get_invoices()))
+}
+
+async fn create_invoice(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Json(req): Json<InvoiceRequest>
+) -> Result<Json<InvoiceView>, ApiError>{
+    let invoice = state.node.write().await.wallet_mut(&wallet)?.create_invoice(
+        req.amount, 
+        req.label, 
+        req.message, 
+        req.expiry
+    );
+    Ok(Json(invoice))
+}
+
+async fn get_invoice(
+    State(state): State<AppState>,
+    WalletName(wallet): WalletName,
+    Path(path): Path<InvoicePath>
+) -> Result<Json<InvoiceView>, ApiError>{
+    state.node.read().await.wallet(&wallet)?.get_invoice(&path.id)
+        .map(Json)
+        .ok_or(ApiError(StatusCode::NOT_FOUND, anyhow!("Invoice not found: {}", path.id)))
+}
+
+async fn list_wallets(State(state): State<AppState>) -> Result<Json<Vec<WalletInfo>>, ApiError>{
+    Ok(Json(state.node.read().await.list_wallets()?))
+}
+
+async fn create_wallet(
+    State(state): State<AppState>,
+    Json(req): Json<CreateWalletRequest>
+) -> Json<serde_json::Value>{
+    match create_node_wallet(&state.node, &req.name, &req.passphrase).await{
+        Ok(phrase) => Json(serde_json::json!({"success": true, "mnemonic": phrase})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    }
+}
+
+async fn load_wallet(
+    State(state): State<AppState>,
+    Json(req): Json<WalletNameRequest>
+) -> Json<serde_json::Value>{
+    match state.node.write().await.load_wallet(&req.name){
+        Ok(()) => Json(serde_json::json!({"success": true})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    }
+}
+
+async fn unload_wallet(
+    State(state): State<AppState>,
+    Json(req): Json<WalletNameRequest>
+) -> Json<serde_json::Value>{
+    match state.node.write().await.unload_wallet(&req.name){
+        Ok(()) => Json(serde_json::json!({"success": true})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    }
+}
+
+async fn set_mining_wallet(
+    State(state): State<AppState>,
+    Json(req): Json<WalletNameRequest>
+) -> Json<serde_json::Value>{
+    match state.node.write().await.set_mining_wallet(&req.name){
+        Ok(()) => Json(serde_json::json!({"success": true})),
+        Err(e) => Json(serde_json::json!({"success": false, "message": e.to_string()})),
+    }
 }
 
 async fn index() -> Html<&'static str>{
@@ -4,10 +4,18 @@ const AddressAddButton = document.getElementById("address_add_button")
 const AddressBook = document.getElementById("address-list")
 const RecipientList = document.getElementById("recipient-list")
 const submit = document.getElementById('submit')
+const preview = document.getElementById('preview')
 const fee = document.getElementById('fee')
+const strategy = document.getElementById('strategy')
 
 let address_book = new Map(); //label : address
 let recipients = []; //label: amount
+let currentWallet = 'default';
+let selectedInputs = new Set(); //outpoints chosen by hand
+
+function walletApi(path){
+    return `/api/wallets/${encodeURIComponent(currentWallet)}/${path}`
+}
 
 function clear_transaction(){
     RecipientList.innerHTML = ''
@@ -51,7 +59,7 @@ function renderAddressBook() {
 
 
 
-function addAddress() {
+async function addAddress() {
     const address = AddressInput.value.trim()
     const label = AddressLabel.value.trim()
 
@@ -67,6 +75,17 @@ function addAddress() {
         alert('please enter unique label')
         return
     }
+    try{
+        const response = await fetch(`/api/validate_address?address=${encodeURIComponent(address)}`);
+        const result = await response.json();
+        if (!result.success){
+            alert(result.message)
+            return
+        }
+    } catch(error) {
+        console.error("Failed to validate address", error)
+        return
+    }
     address_book.set(label, address);
     AddressInput.value = ''
     AddressLabel.value = ''
@@ -140,6 +159,37 @@ function deleteRecipient(index){
     renderRecipients()
 }
 
+//recipients from a payment request are added by address
+async function addPaymentUri() {
+    const input = document.getElementById('payment_uri')
+    try{
+        const response = await fetch(`/api/parse_uri?uri=${encodeURIComponent(input.value.trim())}`);
+        const result = await response.json();
+        if (!result.success){
+            alert(result.message)
+            return
+        }
+        if (result.expired){
+            alert('This payment request has expired')
+            return
+        }
+        const uri = result.uri
+        if (uri.amount === null){
+            amountPopup(uri.address)
+        }else{
+            addRecipient(uri.address, uri.amount)
+        }
+        if (uri.message !== null){
+            showMessage({ success: true, message: uri.label === null ? uri.message : `${uri.label}: ${uri.message}` })
+        }
+        input.value = ''
+    } catch(error) {
+        console.error("Failed to read payment request", error)
+    }
+}
+
+document.getElementById('payment_uri_button').addEventListener('click', addPaymentUri)
+
 function amountPopup(label) {
     const userInput = prompt("Enter amount: ");
     const amount = parseInt(userInput);
@@ -187,49 +237,253 @@ async function  updateStatus() {
         const data = await response.json();
         document.getElementById("height").textContent = data.height
         document.getElementById("mempool").textContent = data.mempool_size
+        document.getElementById("mempool-bytes").textContent = data.mempool_bytes
+        document.getElementById("min-fee-rate").textContent = data.min_fee_rate
         document.getElementById("difficulty").textContent = data.difficulty
     } catch(error) {
         console.error("Failed to fetch node status", error);
     }
     try{
-        const response = await fetch('/api/user_status');
+        const response = await fetch(walletApi('user_status'));
         const data = await response.json();
-        document.getElementById('user-address').textContent = data.pk
-        document.getElementById('funds').textContent = data.amount
+        document.getElementById('user-address').textContent = data.address
+        document.getElementById('funds').textContent = data.confirmed
+        document.getElementById('pending-funds').textContent = data.pending
+        document.getElementById('available-funds').textContent = data.available
+        document.getElementById('watch-funds').textContent = data.watch_only
+        document.getElementById('wallet-state').textContent = (data.locked ? 'Locked' : 'Unlocked')
     } catch(error) {
         console.error("Failed to fetch user status")
     }
 }
 
-submit.addEventListener('click',  async () =>{
-    console.log('Submit button clicked!');  //
+async function newAddress() {
+    try{
+        const response = await fetch(walletApi('new_address'), { method: 'POST' });
+        const data = await response.json();
+        document.getElementById('user-address').textContent = data.address
+    } catch(error) {
+        console.error("Failed to get new address", error)
+    }
+}
+
+document.getElementById('new_address_button').addEventListener('click', newAddress)
+
+async function unlockWallet() {
+    const passphrase = document.getElementById('passphrase').value
+    const timeout = parseInt(document.getElementById('unlock_timeout').value, 10)
+
+    const request = {
+        passphrase: (passphrase === '' ? null : passphrase),
+        timeout: (isNaN(timeout) ? null : timeout),
+        remember: document.getElementById('remember_key').checked
+    };
+
+    try{
+        const response = await fetch(walletApi('wallet/unlock'), {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify(request)
+        });
+        const result = await response.json();
+        if (!result.success){
+            alert(result.message)
+        }
+    } catch(error) {
+        console.error("Failed to unlock wallet", error)
+    }
+    document.getElementById('passphrase').value = ''
+    updateStatus()
+}
+
+async function lockWallet() {
+    try{
+        await fetch(walletApi('wallet/lock'), { method: 'POST' });
+    } catch(error) {
+        console.error("Failed to lock wallet", error)
+    }
+    updateStatus()
+}
+
+async function rescanWallet() {
+    const height = parseInt(document.getElementById('rescan_height').value, 10)
+
+    try{
+        const response = await fetch(walletApi('wallet/rescan'), {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify({ from_height: (isNaN(height) ? 0 : height) })
+        });
+        const result = await response.json();
+        if (!result.success){
+            alert(result.message)
+        }
+    } catch(error) {
+        console.error("Failed to start rescan", error)
+    }
+    updateRescan()
+}
+
+async function updateRescan() {
+    try{
+        const response = await fetch('/api/rescan');
+        const status = await response.json();
+        document.getElementById('rescan-progress').textContent = status.running
+            ? `Rescanning ${status.height}/${status.target}`
+            : '';
+    } catch(error) {
+        console.error("Failed to fetch rescan status", error)
+    }
+}
+
+async function importKey() {
+    const passphrase = document.getElementById('passphrase').value
+
+    const request = {
+        private_key: document.getElementById('import_key').value,
+        passphrase: (passphrase === '' ? null : passphrase),
+        rescan: true
+    };
+
+    try{
+        const response = await fetch(walletApi('wallet/import_key'), {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify(request)
+        });
+        const result = await response.json();
+        if (!result.success){
+            alert(result.message)
+        }
+    } catch(error) {
+        console.error("Failed to import key", error)
+    }
+    document.getElementById('import_key').value = ''
+    document.getElementById('passphrase').value = ''
+    updateStatus()
+}
+
+async function watchAddress() {
+    const request = {
+        address: document.getElementById('watch_address').value,
+        rescan: true
+    };
+
+    try{
+        const response = await fetch(walletApi('wallet/watch'), {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify(request)
+        });
+        const result = await response.json();
+        if (!result.success){
+            alert(result.message)
+        }
+    } catch(error) {
+        console.error("Failed to watch address", error)
+    }
+    document.getElementById('watch_address').value = ''
+    updateStatus()
+}
+
+async function signMessage() {
+    const request = {
+        address: document.getElementById('message_address').value,
+        message: document.getElementById('message_text').value
+    };
+
+    try{
+        const response = await fetch(walletApi('message/sign'), {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify(request)
+        });
+        const result = await response.json();
+        if (result.success) {
+            document.getElementById('message_signature').value = result.signature
+            document.getElementById('message-result').textContent = 'Message signed'
+        } else {
+            document.getElementById('message-result').textContent = result.message
+        }
+    } catch(error) {
+        console.error("Failed to sign message", error)
+    }
+}
+
+async function verifyMessage() {
+    const request = {
+        address: document.getElementById('message_address').value,
+        message: document.getElementById('message_text').value,
+        signature: document.getElementById('message_signature').value
+    };
+
+    try{
+        const response = await fetch('/api/message/verify', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify(request)
+        });
+        const result = await response.json();
+        document.getElementById('message-result').textContent = result.success
+            ? (result.valid ? 'Signature is valid' : 'Signature is NOT valid')
+            : result.message
+    } catch(error) {
+        console.error("Failed to verify message", error)
+    }
+}
+
+document.getElementById('rescan_button').addEventListener('click', rescanWallet)
+document.getElementById('import_button').addEventListener('click', importKey)
+document.getElementById('watch_button').addEventListener('click', watchAddress)
+document.getElementById('sign_message_button').addEventListener('click', signMessage)
+document.getElementById('verify_message_button').addEventListener('click', verifyMessage)
+document.getElementById('unlock_button').addEventListener('click', unlockWallet)
+document.getElementById('lock_button').addEventListener('click', lockWallet)
+
+function transactionRequest(dryRun){
     const feeValue = parseInt(fee.value, 10);
+    const targetValue = parseInt(document.getElementById('target').value, 10);
 
     console.log('recipients array:', recipients);
     console.log('address_book: ', address_book)
 
-    const transaction = {
+    return {
         recipients: recipients.map(([label, amount]) => {
-            const addr = address_book.get(label);
+            const addr = address_book.get(label) ?? label;
             console.log(`Mapping: label="${label}", amount=${amount}, address="${addr}"`);
             return [addr, amount];
         }),
-        fee: feeValue
+        fee: (isNaN(feeValue) ? null : feeValue),
+        target: (isNaN(targetValue) ? null : targetValue),
+        strategy: strategy.value,
+        dry_run: dryRun,
+        replaceable: document.getElementById('replaceable').checked,
+        inputs: Array.from(selectedInputs)
     };
+}
+
+async function sendTransaction(dryRun){
+    const transaction = transactionRequest(dryRun)
 
     try{
-        const response = await fetch('api/transaction', {
+        const response = await fetch(walletApi('transaction'), {
             method: 'POST',
             headers: {
                 'Content-Type': 'application/json'
             },
             body: JSON.stringify(transaction),
         });
-        clear_transaction()
         const text = await response.text();
         
         const result = JSON.parse(text);
-        document.getElementById('message').textContent  = result.message;
+        let message = result.message
+        if (result.plan) {
+            const inputs = result.plan.inputs.map(([outpoint, value]) => `${outpoint} (${value})`).join(', ')
+            message += ` | Inputs: ${inputs} | Fee: ${result.plan.fee} | Change: ${result.plan.change} | Size: ${result.plan.size}`
+        } else if (result.success) {
+            clear_transaction()
+        }
+        document.getElementById('message').textContent  = message;
     
         document.getElementById('message').className = (result.success ? 'success' : 'fail')
         document.getElementById('message').style.display = 'block'
@@ -238,11 +492,381 @@ submit.addEventListener('click',  async () =>{
 
     } catch(error){
         console.error('Caught error:', error);
-    } finally {
-        transaction
     }
+}
+
+function showMessage(result){
+    document.getElementById('message').textContent = result.message
+    document.getElementById('message').className = (result.success ? 'success' : 'fail')
+    document.getElementById('message').style.display = 'block'
+}
+
+async function psbtRequest(url, request){
+    try{
+        const response = await fetch(url, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify(request)
+        });
+        const result = await response.json();
+        if (result.psbt) {
+            document.getElementById('psbt').value = result.psbt
+            result.message += ` | Fee: ${result.fee ?? 'unknown'} | Complete: ${result.complete}`
+        }
+        showMessage(result)
+    } catch(error) {
+        console.error("Failed partially signed transaction request", error)
+    }
+}
+
+function psbtLines(){
+    return document.getElementById('psbt').value.split('\n').map((line) => line.trim()).filter((line) => line !== '')
+}
+
+document.getElementById('psbt_create').addEventListener('click', () => psbtRequest(walletApi('psbt/create'), {
+    ...transactionRequest(false),
+    watch_only: document.getElementById('psbt_watch_only').checked
+}))
+document.getElementById('psbt_sign').addEventListener('click', () => psbtRequest(walletApi('psbt/sign'), { psbt: psbtLines()[0] ?? '' }))
+document.getElementById('psbt_combine').addEventListener('click', () => psbtRequest('/api/psbt/combine', { psbts: psbtLines() }))
+document.getElementById('psbt_broadcast').addEventListener('click', () => psbtRequest('/api/psbt/broadcast', { psbt: psbtLines()[0] ?? '' }))
+document.getElementById('psbt_abandon').addEventListener('click', () => psbtRequest(walletApi('psbt/abandon'), { psbt: psbtLines()[0] ?? '' }))
+
+async function updateHistory() {
+    try{
+        const response = await fetch(walletApi('history'));
+        const history = await response.json();
+        const body = document.getElementById('history-body')
+        body.innerHTML = ''
+
+        history.slice().reverse().forEach((entry) => {
+            const tr = document.createElement('tr')
+            const direction = entry.watch_only ? `${entry.direction} (watch)` : entry.direction
+            const cells = [direction, entry.amount, entry.fee, entry.height, entry.confirmations]
+            cells.forEach((value) => {
+                const td = document.createElement('td')
+                td.textContent = value
+                tr.appendChild(td)
+            })
+
+            const labelCell = document.createElement('td')
+            labelCell.textContent = entry.label ?? ''
+            labelCell.title = entry.txid
+            labelCell.onclick = () => labelPopup(entry.txid)
+            tr.appendChild(labelCell)
+
+            body.appendChild(tr)
+        })
+    } catch(error) {
+        console.error("Failed to fetch history", error)
+    }
+}
+
+async function updateInvoices() {
+    try{
+        const response = await fetch(walletApi('invoices'));
+        const invoices = await response.json();
+        const body = document.getElementById('invoice-body')
+        body.innerHTML = ''
+
+        invoices.slice().reverse().forEach((invoice) => {
+            const tr = document.createElement('tr')
+            const cells = [invoice.label ?? '', invoice.amount ?? 'any', invoice.received, invoice.status]
+            cells.forEach((value) => {
+                const td = document.createElement('td')
+                td.textContent = value
+                tr.appendChild(td)
+            })
+
+            const uriCell = document.createElement('td')
+            const copy = document.createElement('button')
+            copy.textContent = 'Copy'
+            copy.title = invoice.uri
+            copy.onclick = () => navigator.clipboard.writeText(invoice.uri)
+            uriCell.appendChild(copy)
+            tr.appendChild(uriCell)
+
+            body.appendChild(tr)
+        })
+    } catch(error) {
+        console.error("Failed to fetch invoices", error)
+    }
+}
+
+async function createInvoice() {
+    const amount = parseInt(document.getElementById('invoice_amount').value, 10)
+    const expiry = parseInt(document.getElementById('invoice_expiry').value, 10)
+    const label = document.getElementById('invoice_label').value.trim()
+    const message = document.getElementById('invoice_message').value.trim()
+
+    try{
+        const response = await fetch(walletApi('invoices'), {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify({
+                amount: (isNaN(amount) ? null : amount),
+                label: (label === '' ? null : label),
+                message: (message === '' ? null : message),
+                expiry: (isNaN(expiry) ? null : expiry)
+            })
+        });
+        const invoice = await response.json();
+        if (invoice.uri){
+            showMessage({ success: true, message: invoice.uri })
+        }else{
+            alert(invoice.message)
+        }
+    } catch(error) {
+        console.error("Failed to create invoice", error)
+    }
+    updateInvoices()
+}
+
+document.getElementById('invoice_create_button').addEventListener('click', createInvoice)
+
+async function updateUtxos() {
+    try{
+        const response = await fetch(walletApi('utxos'));
+        const utxos = await response.json();
+        const body = document.getElementById('utxo-body')
+        body.innerHTML = ''
+
+        const outpoints = new Set(utxos.map((utxo) => utxo.outpoint))
+        selectedInputs.forEach((outpoint) => {
+            if (!outpoints.has(outpoint)) {
+                selectedInputs.delete(outpoint)
+            }
+        })
+
+        utxos.forEach((utxo) => {
+            const tr = document.createElement('tr')
+            tr.title = utxo.outpoint
+
+            const spendCell = document.createElement('td')
+            const spend = document.createElement('input')
+            spend.type = 'checkbox'
+            spend.checked = selectedInputs.has(utxo.outpoint)
+            spend.disabled = utxo.frozen
+            spend.onchange = () => spend.checked ? selectedInputs.add(utxo.outpoint) : selectedInputs.delete(utxo.outpoint)
+            spendCell.appendChild(spend)
+            tr.appendChild(spendCell)
+
+            const cells = [utxo.value, utxo.pending ? 'pending' : utxo.confirmations, utxo.label ?? '']
+            cells.forEach((value) => {
+                const td = document.createElement('td')
+                td.textContent = value
+                tr.appendChild(td)
+            })
+
+            const frozenCell = document.createElement('td')
+            const frozen = document.createElement('input')
+            frozen.type = 'checkbox'
+            frozen.checked = utxo.frozen
+            frozen.onchange = () => freezeUtxo(utxo.outpoint, frozen.checked)
+            frozenCell.appendChild(frozen)
+            tr.appendChild(frozenCell)
+
+            body.appendChild(tr)
+        })
+    } catch(error) {
+        console.error("Failed to fetch utxos", error)
+    }
+}
+
+async function freezeUtxo(outpoint, frozen) {
+    try{
+        const response = await fetch(walletApi('utxos/freeze'), {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify({ outpoints: [outpoint], frozen: frozen })
+        });
+        const result = await response.json();
+        if (!result.success){
+            alert(result.message)
+        }
+        if (frozen) {
+            selectedInputs.delete(outpoint)
+        }
+    } catch(error) {
+        console.error("Failed to freeze utxo", error)
+    }
+    updateUtxos()
+}
+
+async function consolidate() {
+    const threshold = parseInt(document.getElementById('consolidate_threshold').value, 10)
+    const maxRate = parseInt(document.getElementById('consolidate_max_rate').value, 10)
+    if (isNaN(threshold)) {
+        alert('please enter a value to sweep outputs below')
+        return
+    }
+
+    try{
+        const response = await fetch(walletApi('utxos/consolidate'), {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify({ threshold: threshold, max_rate: (isNaN(maxRate) ? null : maxRate) })
+        });
+        const result = await response.json();
+        showMessage(result)
+    } catch(error) {
+        console.error("Failed to consolidate", error)
+    }
+    updateUtxos()
+}
+
+document.getElementById('consolidate_button').addEventListener('click', consolidate)
+
+async function updatePending() {
+    try{
+        const response = await fetch(walletApi('wallet/pending'));
+        const pending = await response.json();
+        const body = document.getElementById('pending-body')
+        body.innerHTML = ''
+
+        pending.forEach((entry) => {
+            const tr = document.createElement('tr')
+            const cells = [entry.txid.slice(0, 16), entry.fee, entry.fee_rate]
+            cells.forEach((value) => {
+                const td = document.createElement('td')
+                td.textContent = value
+                tr.appendChild(td)
+            })
+
+            const actions = document.createElement('td')
+            if (entry.replaceable) {
+                const bump = document.createElement('button')
+                bump.textContent = 'Bump'
+                bump.onclick = () => bumpFee(walletApi('transaction/bump'), entry.txid)
+                actions.appendChild(bump)
+            }
+            tr.appendChild(actions)
+
+            body.appendChild(tr)
+        })
+    } catch(error) {
+        console.error("Failed to fetch pending transactions", error)
+    }
+}
+
+async function bumpFee(url, txid) {
+    const targetValue = parseInt(document.getElementById('target').value, 10);
+
+    try{
+        const response = await fetch(url, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify({ txid: txid, target: (isNaN(targetValue) ? null : targetValue) })
+        });
+        const result = await response.json();
+        showMessage(result)
+    } catch(error) {
+        console.error("Failed to bump fee", error)
+    }
+    updatePending()
+    updateStatus()
+}
+
+async function updateFeeEstimate() {
+    const targetValue = parseInt(document.getElementById('target').value, 10);
+    const target = (isNaN(targetValue) ? 6 : targetValue)
+
+    try{
+        const response = await fetch(`/api/fee_estimate?target=${target}`);
+        const [estimate] = await response.json();
+        document.getElementById('fee-estimate').textContent =
+            `Estimated rate: ${estimate.fee_rate} per 1000 bytes for ${estimate.target} blocks`
+    } catch(error) {
+        console.error("Failed to fetch fee estimate", error)
+    }
+}
+
+async function labelPopup(txid) {
+    const label = prompt("Enter label: ");
+    if (label === null) {
+        return
+    }
+
+    try{
+        await fetch(walletApi('history/label'), {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify({ txid: txid, label: (label === '' ? null : label) })
+        });
+    } catch(error) {
+        console.error("Failed to set label", error)
+    }
+    updateHistory()
+}
+
+//Wallet Handling -------------------------------------------------------------
+
+async function updateWallets() {
+    try{
+        const response = await fetch('/api/wallets');
+        const wallets = await response.json();
+        const select = document.getElementById('wallet_select')
+        select.innerHTML = ''
+        wallets.forEach((wallet) => {
+            const option = document.createElement('option')
+            option.value = wallet.name
+            option.textContent = wallet.name
+                + (wallet.mining ? ' (mining)' : '')
+                + (wallet.loaded ? '' : ' (unloaded)')
+            select.appendChild(option)
+        });
+        select.value = currentWallet
+    } catch(error) {
+        console.error("Failed to fetch wallets", error)
+    }
+}
+
+function selectWallet(name){
+    currentWallet = name
+    document.getElementById('export_csv').href = walletApi('history/export?format=csv')
+    document.getElementById('export_json').href = walletApi('history/export?format=json')
+    updateStatus()
+    updateHistory()
+    updatePending()
+    updateInvoices()
+    updateUtxos()
+}
+
+async function walletRequest(url, request){
+    try{
+        const response = await fetch(url, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json'},
+            body: JSON.stringify(request)
+        });
+        const result = await response.json();
+        if (!result.success){
+            alert(result.message)
+        } else if (result.mnemonic) {
+            alert(`Write down the recovery phrase for the new wallet: ${result.mnemonic}`)
+        }
+    } catch(error) {
+        console.error("Wallet request failed", error)
+    }
+    updateWallets()
+    selectWallet(currentWallet)
+}
+
+document.getElementById('wallet_select').addEventListener('change', (event) => selectWallet(event.target.value))
+document.getElementById('wallet_load_button').addEventListener('click', () => walletRequest('/api/wallets/load', { name: currentWallet }))
+document.getElementById('wallet_unload_button').addEventListener('click', () => walletRequest('/api/wallets/unload', { name: currentWallet }))
+document.getElementById('wallet_mining_button').addEventListener('click', () => walletRequest('/api/wallets/mining', { name: currentWallet }))
+document.getElementById('wallet_create_button').addEventListener('click', async () => {
+    const name = document.getElementById('wallet_name').value.trim()
+    const passphrase = document.getElementById('wallet_passphrase').value
+    document.getElementById('wallet_name').value = ''
+    document.getElementById('wallet_passphrase').value = ''
+    currentWallet = name
+    await walletRequest('/api/wallets/create', { name: name, passphrase: passphrase })
+})
 
-});
+submit.addEventListener('click', () => sendTransaction(false));
+preview.addEventListener('click', () => sendTransaction(true));
 
 setInterval(async() => {
     try{
@@ -258,10 +882,23 @@ setInterval(async() => {
     }
 })
 setInterval(updateStatus, 2000);
+setInterval(updateHistory, 10000);
+setInterval(updateFeeEstimate, 10000);
+setInterval(updatePending, 5000);
+setInterval(updateRescan, 2000);
+setInterval(updateWallets, 10000);
+setInterval(updateInvoices, 10000);
+setInterval(updateUtxos, 5000);
 
 //Initial function calls---------------------------------------------------------------------
 renderAddressBook()
 renderRecipients()
 updateStatus()
+updateHistory()
+updateFeeEstimate()
+updatePending()
+updateWallets()
+updateInvoices()
+updateUtxos()
 
 document.addEventListener('DOMContentLoaded', loadAddressBook);
\ No newline at end of file